@@ -22,19 +22,11 @@ impl Attachment {
     /// data.
     #[napi]
     pub fn encrypt(array: Uint8Array) -> napi::Result<EncryptedAttachment> {
-        let buffer: &[u8] = array.deref();
-
-        let mut cursor = Cursor::new(buffer);
-        let mut encryptor = matrix_sdk_crypto::AttachmentEncryptor::new(&mut cursor);
-
-        let mut encrypted_data = Vec::new();
-        encryptor.read_to_end(&mut encrypted_data).map_err(into_err)?;
-
-        let media_encryption_info = Some(encryptor.finish());
+        let (encrypted_data, media_encryption_info) = encrypt(array.deref())?;
 
         Ok(EncryptedAttachment {
             encrypted_data: Uint8Array::new(encrypted_data),
-            media_encryption_info,
+            media_encryption_info: Some(media_encryption_info),
         })
     }
 
@@ -57,18 +49,38 @@ impl Attachment {
             ));
         };
 
-        let encrypted_data: &[u8] = attachment.encrypted_data.deref();
+        Ok(Uint8Array::new(decrypt(attachment.encrypted_data.deref(), media_encryption_info)?))
+    }
+}
+
+/// Encrypt a buffer, returning the encrypted data and the media encryption
+/// info needed to decrypt it.
+pub(crate) fn encrypt(
+    buffer: &[u8],
+) -> napi::Result<(Vec<u8>, matrix_sdk_crypto::MediaEncryptionInfo)> {
+    let mut cursor = Cursor::new(buffer);
+    let mut encryptor = matrix_sdk_crypto::AttachmentEncryptor::new(&mut cursor);
 
-        let mut cursor = Cursor::new(encrypted_data);
-        let mut decryptor =
-            matrix_sdk_crypto::AttachmentDecryptor::new(&mut cursor, media_encryption_info)
-                .map_err(into_err)?;
+    let mut encrypted_data = Vec::new();
+    encryptor.read_to_end(&mut encrypted_data).map_err(into_err)?;
 
-        let mut decrypted_data = Vec::new();
-        decryptor.read_to_end(&mut decrypted_data).map_err(into_err)?;
+    Ok((encrypted_data, encryptor.finish()))
+}
 
-        Ok(Uint8Array::new(decrypted_data))
-    }
+/// Decrypt a buffer encrypted by `encrypt`, with its media encryption info.
+pub(crate) fn decrypt(
+    encrypted_data: &[u8],
+    media_encryption_info: matrix_sdk_crypto::MediaEncryptionInfo,
+) -> napi::Result<Vec<u8>> {
+    let mut cursor = Cursor::new(encrypted_data);
+    let mut decryptor =
+        matrix_sdk_crypto::AttachmentDecryptor::new(&mut cursor, media_encryption_info)
+            .map_err(into_err)?;
+
+    let mut decrypted_data = Vec::new();
+    decryptor.read_to_end(&mut decrypted_data).map_err(into_err)?;
+
+    Ok(decrypted_data)
 }
 
 /// An encrypted attachment, usually created from `Attachment.encrypt`.
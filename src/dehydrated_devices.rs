@@ -0,0 +1,212 @@
+//! Dehydrated devices ([MSC3814]).
+//!
+//! A dehydrated device is a device kept on the server, encrypted with a
+//! pickle key, which receives to-device events, and notably room keys,
+//! while the user has no other device online. It is later rehydrated by a
+//! new device of the user, which imports the room keys it has received.
+//!
+//! [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+
+use std::ops::Deref;
+
+use matrix_sdk_crypto::{
+    dehydrated_devices, store::types::DehydratedDeviceKey as SdkDehydratedDeviceKey,
+};
+use napi::bindgen_prelude::Uint8Array;
+use napi_derive::*;
+
+use crate::{encryption, identifiers, into_err, requests, sync_events};
+
+/// Struct collecting methods to create and rehydrate dehydrated devices,
+/// see `OlmMachine.dehydratedDevices`.
+#[napi]
+pub struct DehydratedDevices {
+    inner: dehydrated_devices::DehydratedDevices,
+}
+
+impl From<dehydrated_devices::DehydratedDevices> for DehydratedDevices {
+    fn from(inner: dehydrated_devices::DehydratedDevices) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl DehydratedDevices {
+    /// Create a new `DehydratedDevice`, which can be uploaded to the server
+    /// with `DehydratedDevice.keysForUpload`.
+    #[napi]
+    pub async fn create(&self) -> napi::Result<DehydratedDevice> {
+        Ok(self.inner.create().await.map_err(into_err)?.into())
+    }
+
+    /// Rehydrate a dehydrated device, as returned by the
+    /// `GET /dehydrated_device` endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `pickle_key`, the key the dehydrated device has been encrypted with.
+    /// * `device_id`, the ID of the dehydrated device.
+    /// * `device_data`, the JSON-encoded `device_data` of the dehydrated
+    ///   device.
+    #[napi(strict)]
+    pub async fn rehydrate(
+        &self,
+        pickle_key: &DehydratedDeviceKey,
+        device_id: &identifiers::DeviceId,
+        device_data: String,
+    ) -> napi::Result<RehydratedDevice> {
+        let device_data = serde_json::from_str(device_data.as_str()).map_err(into_err)?;
+
+        Ok(self
+            .inner
+            .rehydrate(&pickle_key.inner, &device_id.inner, device_data)
+            .await
+            .map_err(into_err)?
+            .into())
+    }
+
+    /// Get the cached dehydrated device pickle key, if any.
+    ///
+    /// Returns `null` if no key has been saved with
+    /// `saveDehydratedDeviceKey`.
+    #[napi]
+    pub async fn get_dehydrated_device_key(&self) -> napi::Result<Option<DehydratedDeviceKey>> {
+        Ok(self.inner.get_dehydrated_device_pickle_key().await.map_err(into_err)?.map(Into::into))
+    }
+
+    /// Store the dehydrated device pickle key in the crypto store, so that
+    /// the dehydrated device can be replaced later without asking the user
+    /// for the key again.
+    #[napi(strict)]
+    pub async fn save_dehydrated_device_key(
+        &self,
+        dehydrated_device_key: &DehydratedDeviceKey,
+    ) -> napi::Result<()> {
+        self.inner
+            .save_dehydrated_device_pickle_key(&dehydrated_device_key.inner)
+            .await
+            .map_err(into_err)
+    }
+
+    /// Delete the dehydrated device pickle key from the crypto store.
+    #[napi]
+    pub async fn delete_dehydrated_device_key(&self) -> napi::Result<()> {
+        self.inner.delete_dehydrated_device_pickle_key().await.map_err(into_err)
+    }
+}
+
+/// A dehydrated device, that can be uploaded to the server.
+#[napi]
+pub struct DehydratedDevice {
+    inner: dehydrated_devices::DehydratedDevice,
+}
+
+impl From<dehydrated_devices::DehydratedDevice> for DehydratedDevice {
+    fn from(inner: dehydrated_devices::DehydratedDevice) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl DehydratedDevice {
+    /// Get the request to upload the dehydrated device.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_device_display_name`, the display name of the dehydrated
+    ///   device.
+    /// * `pickle_key`, the key used to encrypt the dehydrated device.
+    #[napi(strict)]
+    pub async fn keys_for_upload(
+        &self,
+        initial_device_display_name: String,
+        pickle_key: &DehydratedDeviceKey,
+    ) -> napi::Result<requests::PutDehydratedDeviceRequest> {
+        let request = self
+            .inner
+            .keys_for_upload(initial_device_display_name, &pickle_key.inner)
+            .await
+            .map_err(into_err)?;
+
+        requests::PutDehydratedDeviceRequest::try_from(&request)
+    }
+}
+
+/// A rehydrated device, that can receive the to-device events sent to the
+/// dehydrated device.
+#[napi]
+pub struct RehydratedDevice {
+    inner: dehydrated_devices::RehydratedDevice,
+}
+
+impl From<dehydrated_devices::RehydratedDevice> for RehydratedDevice {
+    fn from(inner: dehydrated_devices::RehydratedDevice) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl RehydratedDevice {
+    /// Receive the to-device events sent to the dehydrated device.
+    ///
+    /// The room keys they contain are imported into the crypto store of the
+    /// `OlmMachine` the device has been rehydrated by.
+    ///
+    /// # Arguments
+    ///
+    /// * `events`, the JSON-encoded to-device events, as returned by the `POST
+    ///   /dehydrated_device/{device_id}/events` endpoint.
+    /// * `decryption_settings`, the settings used to decrypt the events; by
+    ///   default, the sender's device doesn't need to be trusted.
+    #[napi(strict)]
+    pub async fn receive_events(
+        &self,
+        events: String,
+        decryption_settings: Option<&encryption::DecryptionSettings>,
+    ) -> napi::Result<Vec<sync_events::ProcessedToDeviceEvent>> {
+        let events = serde_json::from_str(events.as_str()).map_err(into_err)?;
+        let decryption_settings = matrix_sdk_crypto::DecryptionSettings::from(decryption_settings);
+
+        Ok(self
+            .inner
+            .receive_events(events, &decryption_settings)
+            .await
+            .map_err(into_err)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+/// The key used to encrypt a dehydrated device.
+#[napi]
+pub struct DehydratedDeviceKey {
+    inner: SdkDehydratedDeviceKey,
+}
+
+impl From<SdkDehydratedDeviceKey> for DehydratedDeviceKey {
+    fn from(inner: SdkDehydratedDeviceKey) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl DehydratedDeviceKey {
+    /// Generate a new random key.
+    #[napi]
+    pub fn create_random_key() -> napi::Result<DehydratedDeviceKey> {
+        Ok(SdkDehydratedDeviceKey::new().map_err(into_err)?.into())
+    }
+
+    /// Create a key from a 32-bytes `Uint8Array`.
+    #[napi]
+    pub fn create_key_from_array(array: Uint8Array) -> napi::Result<DehydratedDeviceKey> {
+        Ok(SdkDehydratedDeviceKey::from_slice(array.deref()).map_err(into_err)?.into())
+    }
+
+    /// Encode the key as base64.
+    #[napi]
+    pub fn to_base64(&self) -> String {
+        self.inner.to_base64()
+    }
+}
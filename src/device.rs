@@ -1,6 +1,17 @@
 //! Information about a device
 
+use std::collections::BTreeMap;
+
+use matrix_sdk_common::ruma::{
+    api::client::keys::claim_keys::v3::Request as RumaKeysClaimRequest,
+    events::AnyToDeviceEventContent, serde::Raw, OneTimeKeyAlgorithm, TransactionId,
+};
+use matrix_sdk_crypto::{types::requests::ToDeviceRequest, OlmError};
+use napi::bindgen_prelude::Generator;
 use napi_derive::napi;
+use serde_json::Value;
+
+use crate::{encryption, identifiers, into_err, requests, vodozemac};
 
 /// A device represents a E2EE capable client of an user.
 #[napi]
@@ -31,6 +42,195 @@ impl Device {
     pub fn is_cross_signed_by_owner(&self) -> bool {
         self.inner.is_cross_signed_by_owner()
     }
+
+    /// Is this device considered to be verified using local trust.
+    #[napi]
+    pub fn is_locally_trusted(&self) -> bool {
+        self.inner.is_locally_trusted()
+    }
+
+    /// Is the device locally marked as blacklisted?
+    ///
+    /// Blacklisted devices won't receive any group sessions.
+    #[napi]
+    pub fn is_blacklisted(&self) -> bool {
+        self.inner.is_blacklisted()
+    }
+
+    /// Is the device deleted?
+    #[napi]
+    pub fn is_deleted(&self) -> bool {
+        self.inner.is_deleted()
+    }
+
+    /// The user ID of the device owner.
+    #[napi(getter)]
+    pub fn user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.user_id().to_owned())
+    }
+
+    /// The unique ID of the device.
+    #[napi(getter)]
+    pub fn device_id(&self) -> identifiers::DeviceId {
+        identifiers::DeviceId::from(self.inner.device_id().to_owned())
+    }
+
+    /// Get the human readable name of the device.
+    #[napi(getter)]
+    pub fn display_name(&self) -> Option<String> {
+        self.inner.display_name().map(ToOwned::to_owned)
+    }
+
+    /// Get the Curve25519 key of the given device.
+    #[napi(getter)]
+    pub fn curve25519_key(&self) -> Option<vodozemac::Curve25519PublicKey> {
+        self.inner.curve25519_key().map(Into::into)
+    }
+
+    /// Get the Ed25519 key of the given device.
+    #[napi(getter)]
+    pub fn ed25519_key(&self) -> Option<vodozemac::Ed25519PublicKey> {
+        self.inner.ed25519_key().map(Into::into)
+    }
+
+    /// Get the list of algorithms this device supports.
+    ///
+    /// Algorithms unknown to this binding are omitted.
+    #[napi(getter)]
+    pub fn algorithms(&self) -> Vec<encryption::EncryptionAlgorithm> {
+        self.inner
+            .algorithms()
+            .iter()
+            .filter_map(|algorithm| algorithm.clone().try_into().ok())
+            .collect()
+    }
+
+    /// Timestamp representing the first time this device has been seen,
+    /// in milliseconds since the Unix epoch.
+    #[napi(getter)]
+    pub fn first_time_seen(&self) -> f64 {
+        u64::from(self.inner.first_time_seen_ts().0) as f64
+    }
+
+    /// Get the trust state of the device.
+    #[napi(getter)]
+    pub fn local_trust_state(&self) -> LocalTrust {
+        self.inner.local_trust_state().into()
+    }
+
+    /// Set the local trust state of the device to the given state.
+    ///
+    /// This won't affect any cross signing trust state, this only sets
+    /// a flag marking to have the given trust state.
+    ///
+    /// # Arguments
+    ///
+    /// * `trust_state`, the new trust state that should be set for the device.
+    #[napi(strict)]
+    pub async fn set_local_trust(&self, trust_state: LocalTrust) -> napi::Result<()> {
+        self.inner.set_local_trust(trust_state.into()).await.map_err(into_err)
+    }
+
+    /// Mark this device as verified.
+    ///
+    /// Works only if the device is owned by the current user, i.e. it
+    /// signs the device with our self-signing key.
+    ///
+    /// Returns a signature upload request that needs to be sent out.
+    #[napi]
+    pub async fn verify(&self) -> napi::Result<requests::SignatureUploadRequest> {
+        let request = self.inner.verify().await.map_err(into_err)?;
+
+        requests::SignatureUploadRequest::try_from(&request)
+    }
+
+    /// Encrypt a to-device event for this device, with Olm.
+    ///
+    /// If no Olm session exists with this device yet, nothing is encrypted,
+    /// and the returned `ToDeviceEncryptionResult` holds a `KeysClaimRequest`
+    /// to claim a one-time key of this device instead. Once it has been sent
+    /// out and marked as sent, this method must be called again. A device
+    /// without any one-time key left is claimed for again on every call:
+    /// `OlmMachine.encryptToDeviceEvent` gives up on such devices instead.
+    ///
+    /// Otherwise, the returned `ToDeviceEncryptionResult` holds a to-device
+    /// request, sending an `m.room.encrypted` event to this device, that
+    /// needs to be sent out.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type`, the type of the event to encrypt.
+    /// * `content`, the JSON-encoded content of the event to encrypt.
+    /// * `share_strategy`, the strategy used to decide whether the device is
+    ///   allowed to receive the event, `CollectStrategy.AllDevices` by default.
+    #[napi(strict)]
+    pub async fn encrypt_to_device_event(
+        &self,
+        event_type: String,
+        content: String,
+        share_strategy: Option<encryption::CollectStrategy>,
+    ) -> napi::Result<encryption::ToDeviceEncryptionResult> {
+        let content: Value = serde_json::from_str(&content).map_err(into_err)?;
+
+        let encrypted = match self.encrypt(&event_type, &content, share_strategy).await {
+            Ok(encrypted) => encrypted,
+
+            Err(OlmError::MissingSession) => {
+                let request = RumaKeysClaimRequest::new(BTreeMap::from([(
+                    self.inner.user_id().to_owned(),
+                    BTreeMap::from([(
+                        self.inner.device_id().to_owned(),
+                        OneTimeKeyAlgorithm::SignedCurve25519,
+                    )]),
+                )]));
+
+                return Ok(encryption::ToDeviceEncryptionResult {
+                    keys_claim_request: Some(requests::KeysClaimRequest::try_from((
+                        TransactionId::new().to_string(),
+                        &request,
+                    ))?),
+                    to_device_request: None,
+                    failed_devices: Vec::new(),
+                });
+            }
+
+            Err(error) => return Err(into_err(error)),
+        };
+
+        let request = ToDeviceRequest::new(
+            self.inner.user_id(),
+            self.inner.device_id().to_owned(),
+            "m.room.encrypted",
+            encrypted,
+        );
+
+        Ok(encryption::ToDeviceEncryptionResult {
+            keys_claim_request: None,
+            to_device_request: Some(requests::ToDeviceRequest::try_from((
+                request.txn_id.to_string(),
+                &request,
+            ))?),
+            failed_devices: Vec::new(),
+        })
+    }
+}
+
+impl Device {
+    /// Encrypt the content of a to-device event for this device, with Olm.
+    pub(crate) async fn encrypt(
+        &self,
+        event_type: &str,
+        content: &Value,
+        share_strategy: Option<encryption::CollectStrategy>,
+    ) -> Result<Raw<AnyToDeviceEventContent>, OlmError> {
+        let share_strategy = share_strategy.unwrap_or(encryption::CollectStrategy::AllDevices);
+
+        Ok(self
+            .inner
+            .encrypt_event_raw(event_type, content, share_strategy.into())
+            .await?
+            .cast_unchecked())
+    }
 }
 
 impl From<matrix_sdk_crypto::Device> for Device {
@@ -38,3 +238,109 @@ impl From<matrix_sdk_crypto::Device> for Device {
         Self { inner: value }
     }
 }
+
+/// The local trust state of a device.
+#[napi]
+pub enum LocalTrust {
+    /// The device has been verified and is trusted.
+    Verified,
+
+    /// The device been blacklisted from communicating.
+    BlackListed,
+
+    /// The trust state of the device is being ignored.
+    Ignored,
+
+    /// The trust state is unset.
+    Unset,
+}
+
+impl From<matrix_sdk_crypto::LocalTrust> for LocalTrust {
+    fn from(value: matrix_sdk_crypto::LocalTrust) -> Self {
+        use matrix_sdk_crypto::LocalTrust::*;
+
+        match value {
+            Verified => Self::Verified,
+            BlackListed => Self::BlackListed,
+            Ignored => Self::Ignored,
+            Unset => Self::Unset,
+        }
+    }
+}
+
+impl From<LocalTrust> for matrix_sdk_crypto::LocalTrust {
+    fn from(value: LocalTrust) -> Self {
+        use LocalTrust::*;
+
+        match value {
+            Verified => Self::Verified,
+            BlackListed => Self::BlackListed,
+            Ignored => Self::Ignored,
+            Unset => Self::Unset,
+        }
+    }
+}
+
+/// A read only view over all devices belonging to a user.
+///
+/// It is iterable, yielding all the devices of the user, e.g. with
+/// `for (const device of userDevices)`.
+#[napi(iterator)]
+pub struct UserDevices {
+    pub(crate) inner: matrix_sdk_crypto::UserDevices,
+    /// The position of the next device yielded by the iterator.
+    next_device: usize,
+}
+
+#[napi]
+impl UserDevices {
+    /// Get the specific device with the given device ID.
+    #[napi(strict)]
+    pub fn get(&self, device_id: &identifiers::DeviceId) -> Option<Device> {
+        self.inner.get(&device_id.inner).map(Into::into)
+    }
+
+    /// Returns true if there is at least one devices of this user
+    /// that is considered to be verified, false otherwise.
+    ///
+    /// This won't consider your own device as verified, as your own
+    /// device is always implicitly verified.
+    #[napi]
+    pub fn is_any_verified(&self) -> bool {
+        self.inner.is_any_verified()
+    }
+
+    /// Array over all the device IDs of the user devices.
+    #[napi]
+    pub fn keys(&self) -> Vec<identifiers::DeviceId> {
+        self.inner.keys().map(|device_id| device_id.to_owned().into()).collect()
+    }
+
+    /// Array over all the devices of the user devices.
+    #[napi]
+    pub fn devices(&self) -> Vec<Device> {
+        self.inner.devices().map(Into::into).collect()
+    }
+}
+
+impl Generator for UserDevices {
+    type Yield = Device;
+    type Next = ();
+    type Return = ();
+
+    fn next(&mut self, _value: Option<Self::Next>) -> Option<Self::Yield> {
+        let device = self.inner.devices().nth(self.next_device);
+
+        // Start over once all the devices have been yielded, so that the
+        // devices can be iterated over again.
+        self.next_device = if device.is_some() { self.next_device + 1 } else { 0 };
+
+        device.map(Into::into)
+    }
+}
+
+impl From<matrix_sdk_crypto::UserDevices> for UserDevices {
+    fn from(value: matrix_sdk_crypto::UserDevices) -> Self {
+        Self { inner: value, next_device: 0 }
+    }
+}
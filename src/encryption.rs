@@ -1,12 +1,13 @@
-use std::time::Duration;
+use std::{ops::Deref, sync::Arc, time::Duration};
 
 use matrix_sdk_common::deserialized_responses::{
     ShieldState as RustShieldState, ShieldStateCode as RustShieldStateCode,
 };
+use matrix_sdk_crypto::types::requests::ToDeviceRequest;
 use napi::bindgen_prelude::BigInt;
 use napi_derive::*;
 
-use crate::events;
+use crate::{events, identifiers, requests};
 
 /// An encryption algorithm to be used to encrypt messages sent to a
 /// room.
@@ -30,15 +31,23 @@ impl From<EncryptionAlgorithm> for matrix_sdk_crypto::types::EventEncryptionAlgo
     }
 }
 
-impl From<matrix_sdk_crypto::types::EventEncryptionAlgorithm> for EncryptionAlgorithm {
-    fn from(value: matrix_sdk_crypto::types::EventEncryptionAlgorithm) -> Self {
+impl TryFrom<matrix_sdk_crypto::types::EventEncryptionAlgorithm> for EncryptionAlgorithm {
+    type Error = napi::Error;
+
+    fn try_from(
+        value: matrix_sdk_crypto::types::EventEncryptionAlgorithm,
+    ) -> Result<Self, Self::Error> {
         use matrix_sdk_crypto::types::EventEncryptionAlgorithm::*;
 
-        match value {
+        Ok(match value {
             OlmV1Curve25519AesSha2 => Self::OlmV1Curve25519AesSha2,
             MegolmV1AesSha2 => Self::MegolmV1AesSha2,
-            _ => unreachable!("Unknown variant"),
-        }
+            _ => {
+                return Err(napi::Error::from_reason(format!(
+                    "Unknown encryption algorithm `{value}`"
+                )))
+            }
+        })
     }
 }
 
@@ -72,7 +81,7 @@ impl Default for EncryptionSettings {
         let default = matrix_sdk_crypto::olm::EncryptionSettings::default();
 
         Self {
-            algorithm: default.algorithm.into(),
+            algorithm: default.algorithm.try_into().unwrap(),
             rotation_period: {
                 let n: u64 = default.rotation_period.as_micros().try_into().unwrap();
 
@@ -110,6 +119,178 @@ impl From<&EncryptionSettings> for matrix_sdk_crypto::olm::EncryptionSettings {
     }
 }
 
+/// Settings for an encrypted room, persisted in the crypto store.
+///
+/// See `OlmMachine.setRoomSettings` and `OlmMachine.getRoomSettings`.
+#[napi]
+pub struct RoomSettings {
+    /// The encryption algorithm that should be used in the room.
+    ///
+    /// Once set, it cannot be changed to another algorithm, in order to
+    /// prevent downgrade attacks.
+    pub algorithm: EncryptionAlgorithm,
+
+    /// Whether room keys should only be shared with trusted devices, see
+    /// `CollectStrategy.OnlyTrustedDevices`.
+    pub only_allow_trusted_devices: bool,
+
+    /// How long a session should be used before changing it, expressed in
+    /// microseconds, or `null` to use the default.
+    pub rotation_period: Option<BigInt>,
+
+    /// How many messages should be sent before changing the session, or
+    /// `null` to use the default.
+    pub rotation_period_messages: Option<BigInt>,
+
+    /// The history visibility of the room, which decides whether the room
+    /// keys are shared with the devices that can see the room's history, see
+    /// `EncryptionSettings.historyVisibility`.
+    pub history_visibility: events::HistoryVisibility,
+}
+
+#[napi]
+impl RoomSettings {
+    /// Create a new `RoomSettings` with default values.
+    #[napi(constructor)]
+    pub fn new() -> napi::Result<RoomSettings> {
+        matrix_sdk_crypto::store::types::RoomSettings::default().try_into()
+    }
+}
+
+impl From<&RoomSettings> for matrix_sdk_crypto::store::types::RoomSettings {
+    fn from(value: &RoomSettings) -> Self {
+        Self {
+            algorithm: value.algorithm.into(),
+            only_allow_trusted_devices: value.only_allow_trusted_devices,
+            session_rotation_period: value
+                .rotation_period
+                .as_ref()
+                .map(|rotation_period| Duration::from_micros(rotation_period.get_u64().1)),
+            session_rotation_period_messages: value
+                .rotation_period_messages
+                .as_ref()
+                .map(|rotation_period_messages| rotation_period_messages.get_u64().1 as usize),
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<matrix_sdk_crypto::store::types::RoomSettings> for RoomSettings {
+    type Error = napi::Error;
+
+    fn try_from(value: matrix_sdk_crypto::store::types::RoomSettings) -> Result<Self, Self::Error> {
+        Ok(Self {
+            algorithm: value.algorithm.try_into()?,
+            only_allow_trusted_devices: value.only_allow_trusted_devices,
+            rotation_period: value.session_rotation_period.map(|rotation_period| {
+                let n: u64 = rotation_period.as_micros().try_into().unwrap();
+
+                n.into()
+            }),
+            rotation_period_messages: value
+                .session_rotation_period_messages
+                .map(|rotation_period_messages| (rotation_period_messages as u64).into()),
+            // The store's `RoomSettings` doesn't hold the history visibility,
+            // it's saved next to them by `OlmMachine.setRoomSettings`.
+            history_visibility: matrix_sdk_crypto::olm::EncryptionSettings::default()
+                .history_visibility
+                .into(),
+        })
+    }
+}
+
+impl From<&RoomSettings> for matrix_sdk_crypto::olm::EncryptionSettings {
+    fn from(value: &RoomSettings) -> Self {
+        let default = Self::default();
+
+        Self {
+            algorithm: value.algorithm.into(),
+            rotation_period: value
+                .rotation_period
+                .as_ref()
+                .map(|rotation_period| Duration::from_micros(rotation_period.get_u64().1))
+                .unwrap_or(default.rotation_period),
+            rotation_period_msgs: value
+                .rotation_period_messages
+                .as_ref()
+                .map(|rotation_period_messages| rotation_period_messages.get_u64().1)
+                .unwrap_or(default.rotation_period_msgs),
+            history_visibility: value.history_visibility.into(),
+            sharing_strategy: if value.only_allow_trusted_devices {
+                matrix_sdk_crypto::CollectStrategy::OnlyTrustedDevices
+            } else {
+                default.sharing_strategy
+            },
+            ..default
+        }
+    }
+}
+
+/// The result of `OlmMachine.shareRoomKey`.
+#[napi]
+pub struct RoomKeyShareResult {
+    /// The to-device requests that need to be sent out, both to share the
+    /// room key and to send the `m.room_key.withheld` notifications to the
+    /// devices that aren't allowed to receive it.
+    #[napi(readonly)]
+    pub requests: Vec<requests::ToDeviceRequest>,
+}
+
+impl TryFrom<Vec<Arc<ToDeviceRequest>>> for RoomKeyShareResult {
+    type Error = napi::Error;
+
+    fn try_from(requests: Vec<Arc<ToDeviceRequest>>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            requests: requests
+                .iter()
+                .map(|request| requests::ToDeviceRequest::try_from(request.deref()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// The result of `OlmMachine.encryptToDeviceEvent` and
+/// `Device.encryptToDeviceEvent`.
+#[napi]
+pub struct ToDeviceEncryptionResult {
+    /// The request to claim one-time keys for the devices no Olm session
+    /// exists with yet, or `null`.
+    ///
+    /// If set, the event hasn't been encrypted: the request needs to be sent
+    /// out, its response passed to `OlmMachine.markRequestAsSent`, and the
+    /// event encrypted again.
+    #[napi(readonly)]
+    pub keys_claim_request: Option<requests::KeysClaimRequest>,
+
+    /// The request sending an `m.room.encrypted` event to all the devices
+    /// the event has been encrypted for, that needs to be sent out; or
+    /// `null` if it hasn't been encrypted for any device.
+    #[napi(readonly)]
+    pub to_device_request: Option<requests::ToDeviceRequest>,
+
+    /// The devices the event couldn't be encrypted for, e.g. because no Olm
+    /// session could be established with them.
+    #[napi(readonly)]
+    pub failed_devices: Vec<ToDeviceEncryptionFailure>,
+}
+
+/// A device a to-device event couldn't be encrypted for.
+#[napi]
+#[derive(Clone)]
+pub struct ToDeviceEncryptionFailure {
+    /// The user ID of the device's owner.
+    #[napi(readonly)]
+    pub user_id: identifiers::UserId,
+
+    /// The device ID.
+    #[napi(readonly)]
+    pub device_id: identifiers::DeviceId,
+
+    /// Why the event couldn't be encrypted for the device.
+    #[napi(readonly)]
+    pub reason: String,
+}
+
 /// Strategy to collect the devices that should receive room keys for the
 /// current discussion.
 #[napi]
@@ -192,6 +373,62 @@ impl Into<matrix_sdk_crypto::CollectStrategy> for CollectStrategy {
     }
 }
 
+/// The level of trust in the sender's device that is required to decrypt an
+/// event.
+#[napi]
+pub enum TrustRequirement {
+    /// Decrypt events from everyone regardless of trust.
+    Untrusted,
+
+    /// Only decrypt events from cross-signed devices or legacy sessions (Megolm
+    /// sessions created before we started collecting trust information).
+    CrossSignedOrLegacy,
+
+    /// Only decrypt events from cross-signed devices.
+    CrossSigned,
+}
+
+impl From<TrustRequirement> for matrix_sdk_crypto::TrustRequirement {
+    fn from(value: TrustRequirement) -> Self {
+        use TrustRequirement::*;
+
+        match value {
+            Untrusted => Self::Untrusted,
+            CrossSignedOrLegacy => Self::CrossSignedOrLegacy,
+            CrossSigned => Self::CrossSigned,
+        }
+    }
+}
+
+/// Settings for decrypting messages.
+#[napi]
+pub struct DecryptionSettings {
+    /// The trust level in the sender's device that is required to decrypt the
+    /// event. If the sender's device is not sufficiently trusted, a
+    /// `MegolmDecryptionError` will be raised.
+    pub sender_device_trust_requirement: TrustRequirement,
+}
+
+#[napi]
+impl DecryptionSettings {
+    /// Create a new `DecryptionSettings` with the given trust requirement.
+    #[napi(constructor)]
+    pub fn new(sender_device_trust_requirement: TrustRequirement) -> DecryptionSettings {
+        Self { sender_device_trust_requirement }
+    }
+}
+
+impl From<Option<&DecryptionSettings>> for matrix_sdk_crypto::DecryptionSettings {
+    fn from(value: Option<&DecryptionSettings>) -> Self {
+        Self {
+            sender_device_trust_requirement: value
+                .map(|value| value.sender_device_trust_requirement)
+                .unwrap_or(TrustRequirement::Untrusted)
+                .into(),
+        }
+    }
+}
+
 /// Take a look at [`matrix_sdk_common::deserialized_responses::ShieldState`]
 /// for more info.
 #[napi]
@@ -1,3 +1,8 @@
+use matrix_sdk_common::ruma::serde::Raw;
+use matrix_sdk_crypto::{vodozemac::megolm::DecryptionError, MegolmError};
+use napi::Env;
+use napi_derive::napi;
+
 /// Generic error wrapping `napi::Error`.
 #[derive(Debug)]
 pub struct Error(napi::Error);
@@ -26,3 +31,111 @@ where
 {
     Error::from(error).into()
 }
+
+/// The code of a `MegolmDecryptionError`, raised when a room event cannot
+/// be decrypted.
+#[napi(string_enum)]
+pub enum DecryptionErrorCode {
+    /// The room key is not known, it may arrive later.
+    MissingRoomKey,
+
+    /// The room key has been withheld by the sender, see the
+    /// `withheldCode` property of the error for the reason.
+    WithheldCode,
+
+    /// The room key is known, but it was received at a later message index
+    /// than the message we are trying to decrypt.
+    UnknownMessageIndex,
+
+    /// The sender's identity or device is not trusted enough, as required by
+    /// the `DecryptionSettings`.
+    SenderIdentityNotTrusted,
+
+    /// The identity keys of the sender's device don't match the ones the room
+    /// key has been received with.
+    MismatchedIdentityKeys,
+
+    /// Any other error.
+    UnableToDecrypt,
+}
+
+/// An error raised when a room event cannot be decrypted.
+///
+/// It is thrown as a JavaScript `Error` with the following properties, so
+/// it can't be checked with `instanceof MegolmDecryptionError`: check that
+/// its `name` is `"MegolmDecryptionError"`, then read its `code`.
+#[napi(object)]
+pub struct MegolmDecryptionError {
+    /// Always `MegolmDecryptionError`.
+    #[napi(ts_type = "'MegolmDecryptionError'")]
+    pub name: String,
+
+    /// The description of the error.
+    pub message: String,
+
+    /// Why the event couldn't be decrypted.
+    pub code: DecryptionErrorCode,
+
+    /// The ID of the Megolm session the event has been encrypted with, if
+    /// known.
+    #[napi(ts_type = "string | null")]
+    pub session_id: Option<String>,
+
+    /// The code the sender gave when withholding the room key (e.g.
+    /// `m.unverified`), if `code` is `DecryptionErrorCode.WithheldCode`.
+    #[napi(ts_type = "string | null")]
+    pub withheld_code: Option<String>,
+}
+
+impl MegolmDecryptionError {
+    /// Create a new error, for the given event that failed to decrypt.
+    pub(crate) fn new<T>(event: &Raw<T>, error: MegolmError) -> Self {
+        let (code, withheld_code) = match &error {
+            MegolmError::MissingRoomKey(Some(withheld_code)) => {
+                (DecryptionErrorCode::WithheldCode, Some(withheld_code.as_str().to_owned()))
+            }
+            MegolmError::MissingRoomKey(None) => (DecryptionErrorCode::MissingRoomKey, None),
+            MegolmError::Decryption(DecryptionError::UnknownMessageIndex(..)) => {
+                (DecryptionErrorCode::UnknownMessageIndex, None)
+            }
+            MegolmError::SenderIdentityNotTrusted(..) => {
+                (DecryptionErrorCode::SenderIdentityNotTrusted, None)
+            }
+            MegolmError::MismatchedIdentityKeys(..) => {
+                (DecryptionErrorCode::MismatchedIdentityKeys, None)
+            }
+            _ => (DecryptionErrorCode::UnableToDecrypt, None),
+        };
+
+        let session_id = event
+            .get_field::<serde_json::Value>("content")
+            .ok()
+            .flatten()
+            .and_then(|content| content.get("session_id")?.as_str().map(ToOwned::to_owned));
+
+        Self {
+            name: "MegolmDecryptionError".to_owned(),
+            message: error.to_string(),
+            code,
+            session_id,
+            withheld_code,
+        }
+    }
+
+    /// Turn this error into a JavaScript `Error`, with the additional
+    /// properties.
+    pub(crate) fn into_napi_error(self, env: &Env) -> napi::Error {
+        self.into_js_error(env).unwrap_or_else(|error| error)
+    }
+
+    fn into_js_error(self, env: &Env) -> napi::Result<napi::Error> {
+        let mut error = env.create_error(napi::Error::from_reason(self.message))?;
+
+        error.set_named_property("name", self.name)?;
+        error.set_named_property("code", self.code)?;
+        error.set_named_property("sessionId", self.session_id)?;
+        error.set_named_property("withheldCode", self.withheld_code)?;
+
+        Ok(napi::Error::from(error.into_unknown()))
+    }
+}
@@ -0,0 +1,228 @@
+//! User identities, i.e. the cross-signing keys of users.
+
+use napi_derive::*;
+
+use crate::{identifiers, into_err, requests, verification};
+
+/// Struct representing a cross signing identity of a user.
+///
+/// This is the user identity of a user that is our own.
+#[napi]
+#[derive(Clone)]
+pub struct OwnUserIdentity {
+    pub(crate) inner: matrix_sdk_crypto::OwnUserIdentity,
+}
+
+impl From<matrix_sdk_crypto::OwnUserIdentity> for OwnUserIdentity {
+    fn from(inner: matrix_sdk_crypto::OwnUserIdentity) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl OwnUserIdentity {
+    /// The unique user ID of this identity.
+    #[napi(getter)]
+    pub fn user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.user_id().to_owned())
+    }
+
+    /// Is this user identity verified.
+    #[napi]
+    pub fn is_verified(&self) -> bool {
+        self.inner.is_verified()
+    }
+
+    /// Mark our user identity as verified.
+    ///
+    /// This will mark the identity locally as verified and sign it with our
+    /// own device.
+    ///
+    /// Returns a signature upload request that needs to be sent out.
+    #[napi]
+    pub async fn verify(&self) -> napi::Result<requests::SignatureUploadRequest> {
+        let request = self.inner.verify().await.map_err(into_err)?;
+
+        requests::SignatureUploadRequest::try_from(&request)
+    }
+
+    /// Send a verification request to our other devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `methods`, the verification methods we want to advertise; if not set,
+    ///   the default methods are used.
+    #[napi(strict)]
+    pub async fn request_verification(
+        &self,
+        methods: Option<Vec<verification::VerificationMethod>>,
+    ) -> napi::Result<verification::RequestVerificationResult> {
+        match methods {
+            Some(methods) => {
+                self.inner
+                    .request_verification_with_methods(
+                        methods.into_iter().map(Into::into).collect(),
+                    )
+                    .await
+            }
+            None => self.inner.request_verification().await,
+        }
+        .map_err(into_err)?
+        .try_into()
+    }
+
+    /// Does our user identity trust our own device, i.e. have we signed our
+    /// own device keys with our self-signing key.
+    #[napi]
+    pub async fn trusts_our_own_device(&self) -> napi::Result<bool> {
+        self.inner.trusts_our_own_device().await.map_err(into_err)
+    }
+
+    /// True if we verified our own identity at some point in the past.
+    ///
+    /// To reset this latch back to `false`, one must call
+    /// `withdraw_verification`.
+    #[napi]
+    pub fn was_previously_verified(&self) -> bool {
+        self.inner.was_previously_verified()
+    }
+
+    /// Remove the requirement for this identity to be verified.
+    ///
+    /// If an identity was previously verified and is not anymore it will be
+    /// reported to the user. In order to remove this notice users have to
+    /// verify again or to withdraw the verification requirement.
+    #[napi]
+    pub async fn withdraw_verification(&self) -> napi::Result<()> {
+        self.inner.withdraw_verification().await.map_err(into_err)
+    }
+
+    /// Was this identity previously verified, and is no longer?
+    #[napi]
+    pub fn has_verification_violation(&self) -> bool {
+        self.inner.has_verification_violation()
+    }
+
+    /// Get the master key of the identity, as a JSON-encoded string.
+    #[napi(getter)]
+    pub fn master_key(&self) -> napi::Result<String> {
+        serde_json::to_string(self.inner.master_key().as_ref()).map_err(into_err)
+    }
+
+    /// Get the self-signing key of the identity, as a JSON-encoded string.
+    #[napi(getter)]
+    pub fn self_signing_key(&self) -> napi::Result<String> {
+        serde_json::to_string(self.inner.self_signing_key().as_ref()).map_err(into_err)
+    }
+
+    /// Get the user-signing key of the identity, as a JSON-encoded string.
+    #[napi(getter)]
+    pub fn user_signing_key(&self) -> napi::Result<String> {
+        serde_json::to_string(self.inner.user_signing_key().as_ref()).map_err(into_err)
+    }
+}
+
+/// Struct representing a cross signing identity of a user.
+///
+/// This is the user identity of a user that isn't our own. Other users will
+/// only contain a master key and a self signing key, meaning that only device
+/// signatures can be checked with this identity.
+#[napi]
+#[derive(Clone)]
+pub struct OtherUserIdentity {
+    pub(crate) inner: matrix_sdk_crypto::OtherUserIdentity,
+}
+
+impl From<matrix_sdk_crypto::OtherUserIdentity> for OtherUserIdentity {
+    fn from(inner: matrix_sdk_crypto::OtherUserIdentity) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl OtherUserIdentity {
+    /// The unique user ID of this identity.
+    #[napi(getter)]
+    pub fn user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.user_id().to_owned())
+    }
+
+    /// Is this user identity verified.
+    #[napi]
+    pub fn is_verified(&self) -> bool {
+        self.inner.is_verified()
+    }
+
+    /// Manually verify this user.
+    ///
+    /// This method will attempt to sign the user identity using our private
+    /// cross signing key.
+    ///
+    /// This method fails if we don't have the private part of our user-signing
+    /// key.
+    ///
+    /// Returns a signature upload request that needs to be sent out.
+    #[napi]
+    pub async fn verify(&self) -> napi::Result<requests::SignatureUploadRequest> {
+        let request = self.inner.verify().await.map_err(into_err)?;
+
+        requests::SignatureUploadRequest::try_from(&request)
+    }
+
+    /// Pin the current identity (public part of the master signing key).
+    ///
+    /// This is used to acknowledge an identity change, so that the user is no
+    /// longer warned about it.
+    #[napi]
+    pub async fn pin_current_master_key(&self) -> napi::Result<()> {
+        self.inner.pin_current_master_key().await.map_err(into_err)
+    }
+
+    /// Has the identity changed in a way that requires approval from the
+    /// user?
+    ///
+    /// A user identity needs approval if it changed after the crypto machine
+    /// has already observed ("pinned") a different identity for that user,
+    /// unless it is an explicitly verified identity.
+    #[napi]
+    pub fn identity_needs_user_approval(&self) -> bool {
+        self.inner.identity_needs_user_approval()
+    }
+
+    /// True if we verified this identity at some point in the past.
+    ///
+    /// To reset this latch back to `false`, one must call
+    /// `withdraw_verification`.
+    #[napi]
+    pub fn was_previously_verified(&self) -> bool {
+        self.inner.was_previously_verified()
+    }
+
+    /// Remove the requirement for this identity to be verified.
+    ///
+    /// If an identity was previously verified and is not anymore it will be
+    /// reported to the user. In order to remove this notice users have to
+    /// verify again or to withdraw the verification requirement.
+    #[napi]
+    pub async fn withdraw_verification(&self) -> napi::Result<()> {
+        self.inner.withdraw_verification().await.map_err(into_err)
+    }
+
+    /// Was this identity previously verified, and is no longer?
+    #[napi]
+    pub fn has_verification_violation(&self) -> bool {
+        self.inner.has_verification_violation()
+    }
+
+    /// Get the master key of the identity, as a JSON-encoded string.
+    #[napi(getter)]
+    pub fn master_key(&self) -> napi::Result<String> {
+        serde_json::to_string(self.inner.master_key().as_ref()).map_err(into_err)
+    }
+
+    /// Get the self-signing key of the identity, as a JSON-encoded string.
+    #[napi(getter)]
+    pub fn self_signing_key(&self) -> napi::Result<String> {
+        serde_json::to_string(self.inner.self_signing_key().as_ref()).map_err(into_err)
+    }
+}
@@ -1,34 +1,59 @@
 //! The crypto specific Olm objects.
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     mem::ManuallyDrop,
     ops::Deref,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
-use matrix_sdk_common::ruma::{
-    events::secret::request::SecretName, serde::Raw, OneTimeKeyAlgorithm, OwnedTransactionId, UInt,
+use futures_util::StreamExt;
+use matrix_sdk_common::{
+    deserialized_responses::ProcessedToDeviceEvent,
+    ruma::{
+        api::client::backup::KeyBackupData,
+        events::{
+            room::history_visibility::HistoryVisibility as RumaHistoryVisibility,
+            secret::request::SecretName,
+        },
+        serde::Raw,
+        OneTimeKeyAlgorithm, OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, UInt,
+    },
 };
 use matrix_sdk_crypto::{
-    backups::MegolmV1BackupKey, types::RoomKeyBackupInfo, DecryptionSettings,
-    EncryptionSyncChanges, TrustRequirement,
+    backups::MegolmV1BackupKey,
+    olm::BackedUpRoomKey,
+    store::CryptoStore,
+    types::{
+        events::room_key_bundle::RoomKeyBundleContent, requests::ToDeviceRequest, BackupSecrets,
+        RoomKeyBackupInfo,
+    },
+    DecryptionSettings, EncryptionSyncChanges,
+};
+use napi::{
+    bindgen_prelude::{spawn, within_runtime_if_available, Either, Either6, Promise, Uint8Array},
+    threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    tokio::task::JoinHandle,
+    Env, JsObject,
 };
-use napi::bindgen_prelude::{within_runtime_if_available, Either6};
 use napi_derive::*;
 use serde_json::value::RawValue;
 use zeroize::Zeroize;
 
 use crate::{
+    attachment,
     backup::{BackupDecryptionKey, BackupKeys, RoomKeyCounts},
-    device::Device,
-    encryption, identifiers, into_err, olm, requests,
+    dehydrated_devices,
+    device::{Device, UserDevices},
+    encryption, errors, identifiers, identities, into_err, olm, requests,
     responses::{self, response_from_string},
-    secret_storage::{SecretStorageItems, SecretStorageKey},
-    sync_events,
+    secret_storage::{
+        AccountDataEvent, SecretStorageBootstrapResult, SecretStorageItems, SecretStorageKey,
+    },
+    store, sync_events,
     types::{self, SignatureVerification},
-    vodozemac,
+    verification, vodozemac,
 };
 
 /// The value used by the `OlmMachine` JS class.
@@ -68,6 +93,57 @@ impl Deref for OlmMachineInner {
     }
 }
 
+/// The kinds of callbacks that can be registered on an `OlmMachine`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum CallbackKind {
+    RoomKeyUpdated,
+    RoomKeysWithheld,
+    UserIdentityUpdated,
+    DevicesUpdated,
+}
+
+/// The tasks forwarding the streams of the store to the callbacks registered
+/// on an `OlmMachine`, at most one per kind of callback.
+///
+/// A task is aborted when its callback is replaced by a new one, or when the
+/// tasks are dropped, i.e. when the `OlmMachine` is closed or garbage
+/// collected.
+#[derive(Default)]
+struct CallbackTasks(Mutex<HashMap<CallbackKind, JoinHandle<()>>>);
+
+impl CallbackTasks {
+    fn replace(&self, kind: CallbackKind, task: JoinHandle<()>) {
+        if let Some(previous_task) = self.0.lock().unwrap().insert(kind, task) {
+            previous_task.abort();
+        }
+    }
+}
+
+impl Drop for CallbackTasks {
+    fn drop(&mut self) {
+        for task in self.0.get_mut().unwrap().values() {
+            task.abort();
+        }
+    }
+}
+
+/// The key of the custom value holding the history visibility of a room, which
+/// the `RoomSettings` of the crypto store have no room for.
+fn history_visibility_key(room_id: &RoomId) -> String {
+    format!("matrix-sdk-crypto-nodejs.history_visibility.{room_id}")
+}
+
+/// The type of the to-device event sharing a room key bundle ([MSC4268]).
+///
+/// [MSC4268]: https://github.com/matrix-org/matrix-spec-proposals/pull/4268
+const ROOM_KEY_BUNDLE_EVENT_TYPE: &str = "io.element.msc4268.room_key_bundle";
+
+/// The key of the custom value holding the user who sent the last room key
+/// bundle of a room, which the crypto store only looks up by sender.
+fn room_key_bundle_sender_key(room_id: &RoomId) -> String {
+    format!("matrix-sdk-crypto-nodejs.room_key_bundle_sender.{room_id}")
+}
+
 /// Represents the type of store an `OlmMachine` can use.
 #[derive(Default)]
 #[napi]
@@ -75,6 +151,14 @@ pub enum StoreType {
     /// Use `matrix-sdk-sqlite`.
     #[default]
     Sqlite,
+
+    /// Use a store implemented in JavaScript, see `CustomStore`.
+    Custom,
+
+    /// Keep everything in memory. The state of the machine can be saved
+    /// with `OlmMachine.snapshot`, and restored with
+    /// `OlmMachine.initializeFromSnapshot`.
+    Memory,
 }
 
 /// State machine implementation of the Olm/Megolm encryption protocol
@@ -83,6 +167,13 @@ pub enum StoreType {
 #[napi]
 pub struct OlmMachine {
     inner: OlmMachineInner,
+
+    /// The store of the machine, if it's kept in memory and can be
+    /// snapshotted.
+    memory_store: Option<Arc<store::JsonCryptoStore>>,
+
+    /// The tasks calling the registered callbacks.
+    callback_tasks: CallbackTasks,
 }
 
 #[napi]
@@ -115,6 +206,11 @@ impl OlmMachine {
     ///   data at rest in the store. **Warning**, if no passphrase is given, the
     ///   store and all its data will remain unencrypted. This argument is
     ///   ignored if `store_path` is not set.
+    /// * `store_type`, the type of store to use, `StoreType.Sqlite` by default.
+    ///   `StoreType.Custom` and `StoreType.Memory` cannot be used with a
+    ///   `store_path` or a `store_passphrase`.
+    /// * `custom_store`, the store to use with `StoreType.Custom`, and only
+    ///   with it.
     #[napi(strict)]
     pub async fn initialize(
         user_id: &identifiers::UserId,
@@ -122,39 +218,125 @@ impl OlmMachine {
         store_path: Option<String>,
         mut store_passphrase: Option<String>,
         store_type: Option<StoreType>,
+        custom_store: Option<&store::CustomStore>,
     ) -> napi::Result<OlmMachine> {
         let user_id = user_id.clone().inner;
         let device_id = device_id.clone().inner;
 
         let user_id = user_id.as_ref();
         let device_id = device_id.as_ref();
+        let store_type = store_type.unwrap_or_default();
+
+        let conflict = if custom_store.is_some() && !matches!(store_type, StoreType::Custom) {
+            Some("A `CustomStore` can only be given with `StoreType.Custom`")
+        } else if (store_path.is_some() || store_passphrase.is_some())
+            && !matches!(store_type, StoreType::Sqlite)
+        {
+            Some("A store path or passphrase can only be given with `StoreType.Sqlite`")
+        } else {
+            None
+        };
+
+        if let Some(conflict) = conflict {
+            store_passphrase.zeroize();
+
+            return Err(napi::Error::from_reason(conflict));
+        }
+
+        let mut memory_store = None;
 
         Ok(OlmMachine {
-            inner: OlmMachineInner::Opened(ManuallyDrop::new(match store_path {
-                Some(store_path) => {
-                    let machine = match store_type.unwrap_or_default() {
-                        StoreType::Sqlite => {
-                            let store = matrix_sdk_sqlite::SqliteCryptoStore::open(
-                                store_path,
-                                store_passphrase.as_deref(),
-                            )
-                            .await
-                            .map_err(into_err)?;
+            inner: OlmMachineInner::Opened(ManuallyDrop::new(match (store_type, store_path) {
+                (StoreType::Sqlite, Some(store_path)) => {
+                    let store = matrix_sdk_sqlite::SqliteCryptoStore::open(
+                        store_path,
+                        store_passphrase.as_deref(),
+                    )
+                    .await;
+
+                    store_passphrase.zeroize();
 
-                            matrix_sdk_crypto::OlmMachineBuilder::new(user_id, device_id)
-                                .with_crypto_store(Arc::new(store))
-                                .build()
-                                .await
-                        }
-                    };
+                    matrix_sdk_crypto::OlmMachineBuilder::new(user_id, device_id)
+                        .with_crypto_store(Arc::new(store.map_err(into_err)?))
+                        .build()
+                        .await
+                        .map_err(into_err)?
+                }
+
+                (StoreType::Sqlite, None) => {
+                    matrix_sdk_crypto::OlmMachine::new(user_id, device_id).await
+                }
 
+                (StoreType::Custom, _) => {
                     store_passphrase.zeroize();
 
-                    machine.map_err(into_err)?
+                    let store = custom_store.ok_or_else(|| {
+                        napi::Error::from_reason(
+                            "A `CustomStore` must be given to use `StoreType.Custom`",
+                        )
+                    })?;
+
+                    matrix_sdk_crypto::OlmMachineBuilder::new(user_id, device_id)
+                        .with_crypto_store(store.inner.clone())
+                        .build()
+                        .await
+                        .map_err(into_err)?
                 }
 
-                None => matrix_sdk_crypto::OlmMachine::new(user_id, device_id).await,
+                (StoreType::Memory, _) => {
+                    store_passphrase.zeroize();
+
+                    let store = Arc::new(store::JsonCryptoStore::new_in_memory());
+                    memory_store = Some(store.clone());
+
+                    matrix_sdk_crypto::OlmMachineBuilder::new(user_id, device_id)
+                        .with_crypto_store(store)
+                        .build()
+                        .await
+                        .map_err(into_err)?
+                }
             })),
+            memory_store,
+            callback_tasks: CallbackTasks::default(),
+        })
+    }
+
+    /// Create a new `OlmMachine` asynchronously, from a snapshot of a
+    /// machine created with `StoreType.Memory`.
+    ///
+    /// The user and device IDs are the ones of the snapshotted
+    /// machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot`, the snapshot returned by `OlmMachine.snapshot`.
+    /// * `passphrase`, the passphrase the snapshot has been encrypted with.
+    #[napi(strict)]
+    pub async fn initialize_from_snapshot(
+        snapshot: Uint8Array,
+        mut passphrase: String,
+    ) -> napi::Result<OlmMachine> {
+        let store = store::JsonCryptoStore::from_snapshot(&snapshot, &passphrase);
+        passphrase.zeroize();
+
+        let store = Arc::new(store?);
+        let account = store
+            .load_account()
+            .await
+            .map_err(into_err)?
+            .ok_or_else(|| napi::Error::from_reason("The snapshot contains no account"))?;
+
+        let machine =
+            matrix_sdk_crypto::OlmMachineBuilder::new(account.user_id(), account.device_id())
+                .with_crypto_store(store.clone())
+                .build()
+                .await
+                .map_err(into_err)?;
+
+        Ok(OlmMachine {
+            inner: OlmMachineInner::Opened(ManuallyDrop::new(machine)),
+            memory_store: Some(store),
+            callback_tasks: CallbackTasks::default(),
         })
     }
 
@@ -188,13 +370,9 @@ impl OlmMachine {
 
     /// Handle to-device events and one-time key counts from a sync response.
     ///
-    /// This will decrypt and handle to-device events, returning a two-element
-    /// array where:
-    ///
-    /// * The first element is an array containing the decrypted to-device
-    ///   events as JSON-encoded strings.
-    /// * The second element is an array containing information about room keys
-    ///   received as part of those decrypted to-device events.
+    /// This will decrypt and handle to-device events, returning the processed
+    /// to-device events, and information about the room keys received as part
+    /// of those to-device events.
     ///
     /// To decrypt an event from the room timeline, please use
     /// `decrypt_room_event`.
@@ -208,6 +386,8 @@ impl OlmMachine {
     ///   response returned.
     /// * `unused_fallback_keys`, the list of unused fallback keys the
     ///   homeserver knows about.
+    /// * `decryption_settings`, the settings used to decrypt the to-device
+    ///   events; by default, the sender's devices don't need to be trusted.
     #[napi(strict)]
     pub async fn receive_sync_changes(
         &self,
@@ -215,7 +395,8 @@ impl OlmMachine {
         changed_devices: &sync_events::DeviceLists,
         one_time_key_counts: HashMap<String, u32>,
         unused_fallback_keys: Vec<String>,
-    ) -> napi::Result<String> {
+        decryption_settings: Option<&encryption::DecryptionSettings>,
+    ) -> napi::Result<sync_events::SyncChangesResult> {
         let to_device_events_decoded =
             serde_json::from_str(to_device_events.as_ref()).map_err(into_err)?;
         let changed_devices = changed_devices.inner.clone();
@@ -230,7 +411,7 @@ impl OlmMachine {
                 .collect::<Vec<_>>(),
         );
 
-        let (to_device_events, room_key_info) = &self
+        let (to_device_events, room_key_infos) = self
             .inner
             .receive_sync_changes(
                 EncryptionSyncChanges {
@@ -242,17 +423,44 @@ impl OlmMachine {
                     // matrix-sdk-crypto does not (currently) use `next_batch_token`.
                     next_batch_token: None,
                 },
-                &DecryptionSettings {
-                    sender_device_trust_requirement: TrustRequirement::Untrusted,
-                },
+                &DecryptionSettings::from(decryption_settings),
             )
             .await
             .map_err(into_err)?;
 
-        let to_device_events: Vec<_> =
-            to_device_events.into_iter().map(|event| event.to_raw()).collect();
+        // Remember who sent the room key bundles, for `receiveRoomKeyBundle`.
+        for event in &to_device_events {
+            if !matches!(event, ProcessedToDeviceEvent::Decrypted { .. }) {
+                continue;
+            }
 
-        serde_json::to_string(&(to_device_events, room_key_info)).map_err(into_err)
+            let event: serde_json::Value =
+                serde_json::from_str(event.to_raw().json().get()).map_err(into_err)?;
+
+            if event["type"] != ROOM_KEY_BUNDLE_EVENT_TYPE {
+                continue;
+            }
+
+            let (Some(sender), Some(room_id)) =
+                (event["sender"].as_str(), event["content"]["room_id"].as_str())
+            else {
+                continue;
+            };
+
+            self.inner
+                .store()
+                .set_custom_value(
+                    &room_key_bundle_sender_key(&RoomId::parse(room_id).map_err(into_err)?),
+                    sender.as_bytes().to_vec(),
+                )
+                .await
+                .map_err(into_err)?;
+        }
+
+        Ok(sync_events::SyncChangesResult {
+            to_device_events: to_device_events.into_iter().map(Into::into).collect(),
+            room_key_infos: room_key_infos.into_iter().map(Into::into).collect(),
+        })
     }
 
     /// Get the outgoing requests that need to be sent out.
@@ -397,30 +605,278 @@ impl OlmMachine {
 
     /// Get to-device requests to share a room key with users in a room.
     ///
+    /// Returns a `RoomKeyShareResult`, holding the to-device requests to send
+    /// out, including the `m.room_key.withheld` notifications for the devices
+    /// that aren't allowed to receive the room key.
+    ///
     /// # Arguments
     ///
     /// * `room_id`, the room ID of the room where the room key will be used.
     /// * `users`, the list of users that should receive the room key.
-    /// * `encryption_settings`, the encryption settings.
+    /// * `encryption_settings`, the encryption settings. If omitted, they are
+    ///   derived from the room settings saved with `setRoomSettings`, if any,
+    ///   including the history visibility, or the default ones otherwise.
     #[napi(strict)]
     pub async fn share_room_key(
         &self,
         room_id: &identifiers::RoomId,
         users: Vec<&identifiers::UserId>,
-        encryption_settings: &encryption::EncryptionSettings,
-    ) -> napi::Result<Vec<requests::ToDeviceRequest>> {
+        encryption_settings: Option<&encryption::EncryptionSettings>,
+    ) -> napi::Result<encryption::RoomKeyShareResult> {
+        let encryption_settings = match encryption_settings {
+            Some(encryption_settings) => encryption_settings.into(),
+            // The SDK doesn't keep the history visibility in its room settings,
+            // `getRoomSettings` restores it from the custom value saved with
+            // them, so that the room key follows it.
+            None => self
+                .get_room_settings(room_id)
+                .await?
+                .map(|room_settings| (&room_settings).into())
+                .unwrap_or_default(),
+        };
         let room_id = room_id.inner.clone();
         let users = users.into_iter().map(|user| user.inner.clone()).collect::<Vec<_>>();
-        let encryption_settings =
-            matrix_sdk_crypto::olm::EncryptionSettings::from(encryption_settings);
 
-        self.inner
+        let requests = self
+            .inner
             .share_room_key(&room_id, users.iter().map(AsRef::as_ref), encryption_settings)
             .await
+            .map_err(into_err)?;
+
+        requests.try_into()
+    }
+
+    /// Discard the current outbound group session of a room, if any.
+    ///
+    /// A new one will be created, and shared with the room members, the next
+    /// time `shareRoomKey` is called. This should be done when a member
+    /// leaves the room, or one of the devices gets blacklisted.
+    ///
+    /// The session isn't deleted, but marked as invalidated in the crypto
+    /// store, see `OutboundGroupSessionInfo.invalidated`.
+    ///
+    /// Returns `true` if a session was discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room.
+    #[napi(strict)]
+    pub async fn discard_room_key(&self, room_id: &identifiers::RoomId) -> napi::Result<bool> {
+        let room_id = room_id.inner.clone();
+
+        self.inner.discard_room_key(&room_id).await.map_err(into_err)
+    }
+
+    /// Get information about the current outbound group session of a room,
+    /// as saved in the crypto store, or `null` if there is none.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room.
+    #[napi(strict)]
+    pub async fn get_outbound_group_session_info(
+        &self,
+        room_id: &identifiers::RoomId,
+    ) -> napi::Result<Option<olm::OutboundGroupSessionInfo>> {
+        let room_id = room_id.inner.clone();
+
+        let Some(session) =
+            self.inner.store().get_outbound_group_session(&room_id).await.map_err(into_err)?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(olm::OutboundGroupSessionInfo::new(
+            session.session_id().to_owned(),
+            session.pickle().await,
+        )))
+    }
+
+    /// Save the settings of an encrypted room in the crypto store.
+    ///
+    /// They are used by `shareRoomKey` when no `EncryptionSettings` are
+    /// given. Changing the encryption algorithm of a room which already has
+    /// settings is refused, to prevent downgrade attacks.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room.
+    /// * `room_settings`, the settings of the room.
+    #[napi(strict)]
+    pub async fn set_room_settings(
+        &self,
+        room_id: &identifiers::RoomId,
+        room_settings: &encryption::RoomSettings,
+    ) -> napi::Result<()> {
+        let room_id = room_id.inner.clone();
+        let history_visibility =
+            serde_json::to_vec(&RumaHistoryVisibility::from(room_settings.history_visibility))
+                .map_err(into_err)?;
+        let room_settings = matrix_sdk_crypto::store::types::RoomSettings::from(room_settings);
+
+        self.inner.set_room_settings(&room_id, &room_settings).await.map_err(into_err)?;
+        self.inner
+            .store()
+            .set_custom_value(&history_visibility_key(&room_id), history_visibility)
+            .await
+            .map_err(into_err)
+    }
+
+    /// Get the settings of an encrypted room, as saved with
+    /// `setRoomSettings`, or `null` if there is none.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room.
+    #[napi(strict)]
+    pub async fn get_room_settings(
+        &self,
+        room_id: &identifiers::RoomId,
+    ) -> napi::Result<Option<encryption::RoomSettings>> {
+        let room_id = room_id.inner.clone();
+
+        let Some(room_settings) = self.inner.room_settings(&room_id).await.map_err(into_err)?
+        else {
+            return Ok(None);
+        };
+        let mut room_settings = encryption::RoomSettings::try_from(room_settings)?;
+
+        if let Some(history_visibility) = self
+            .inner
+            .store()
+            .get_custom_value(&history_visibility_key(&room_id))
+            .await
             .map_err(into_err)?
-            .into_iter()
-            .map(|td| requests::ToDeviceRequest::try_from(td.deref()))
-            .collect()
+        {
+            room_settings.history_visibility =
+                serde_json::from_slice::<RumaHistoryVisibility>(&history_visibility)
+                    .map_err(into_err)?
+                    .into();
+        }
+
+        Ok(Some(room_settings))
+    }
+
+    /// Encrypt a to-device event for a set of devices, with Olm.
+    ///
+    /// If an Olm session is missing with some of the given devices, nothing is
+    /// encrypted, and the returned `ToDeviceEncryptionResult` holds a
+    /// `KeysClaimRequest` to claim their one-time keys instead, like
+    /// `getMissingSessions` does. Once it has been sent out and marked as
+    /// sent, this method must be called again.
+    ///
+    /// Otherwise, the returned `ToDeviceEncryptionResult` holds a single
+    /// to-device request, sending an `m.room.encrypted` event to all the
+    /// devices the event has been encrypted for. The devices it couldn't be
+    /// encrypted for, e.g. because the one-time key claim failed for them,
+    /// are skipped and listed in `failedDevices`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type`, the type of the event to encrypt.
+    /// * `content`, the JSON-encoded content of the event to encrypt.
+    /// * `devices`, the devices the event must be encrypted for.
+    /// * `share_strategy`, the strategy used to decide whether the devices are
+    ///   allowed to receive the event, `CollectStrategy.AllDevices` by default.
+    #[napi(strict)]
+    pub async fn encrypt_to_device_event(
+        &self,
+        event_type: String,
+        content: String,
+        devices: Vec<&Device>,
+        share_strategy: Option<encryption::CollectStrategy>,
+    ) -> napi::Result<encryption::ToDeviceEncryptionResult> {
+        let content: serde_json::Value = serde_json::from_str(&content).map_err(into_err)?;
+        let users = devices.iter().map(|device| device.inner.user_id()).collect::<BTreeSet<_>>();
+
+        // The missing sessions are looked up for all the devices of the users,
+        // but only the given devices are claimed for. The request keeps its
+        // transaction ID, so that the devices without one-time keys are given up
+        // on once its response has been received.
+        let missing_sessions = self
+            .inner
+            .get_missing_sessions(users.into_iter())
+            .await
+            .map_err(into_err)?
+            .map(|(transaction_id, mut keys_claim_request)| {
+                keys_claim_request.one_time_keys.retain(|user_id, one_time_keys| {
+                    one_time_keys.retain(|device_id, _| {
+                        devices.iter().any(|device| {
+                            device.inner.user_id() == user_id
+                                && device.inner.device_id() == device_id
+                        })
+                    });
+
+                    !one_time_keys.is_empty()
+                });
+
+                (transaction_id, keys_claim_request)
+            })
+            .filter(|(_, keys_claim_request)| !keys_claim_request.one_time_keys.is_empty());
+
+        if let Some((transaction_id, keys_claim_request)) = missing_sessions {
+            return Ok(encryption::ToDeviceEncryptionResult {
+                keys_claim_request: Some(requests::KeysClaimRequest::try_from((
+                    transaction_id.to_string(),
+                    &keys_claim_request,
+                ))?),
+                to_device_request: None,
+                failed_devices: Vec::new(),
+            });
+        }
+
+        let mut request: Option<ToDeviceRequest> = None;
+        let mut failed_devices = Vec::new();
+
+        for device in devices {
+            let user_id = device.inner.user_id();
+            let device_id = device.inner.device_id().to_owned();
+
+            // Encrypting advances the Olm ratchet of the device, so a failure
+            // for one device mustn't discard what has been encrypted for the
+            // other ones.
+            let encrypted = match device.encrypt(&event_type, &content, share_strategy).await {
+                Ok(encrypted) => encrypted,
+                Err(error) => {
+                    failed_devices.push(encryption::ToDeviceEncryptionFailure {
+                        user_id: user_id.to_owned().into(),
+                        device_id: device_id.into(),
+                        reason: error.to_string(),
+                    });
+
+                    continue;
+                }
+            };
+
+            match &mut request {
+                Some(request) => {
+                    request
+                        .messages
+                        .entry(user_id.to_owned())
+                        .or_default()
+                        .insert(device_id.into(), encrypted);
+                }
+
+                None => {
+                    request = Some(ToDeviceRequest::new(
+                        user_id,
+                        device_id,
+                        "m.room.encrypted",
+                        encrypted,
+                    ));
+                }
+            }
+        }
+
+        Ok(encryption::ToDeviceEncryptionResult {
+            keys_claim_request: None,
+            to_device_request: request
+                .map(|request| {
+                    requests::ToDeviceRequest::try_from((request.txn_id.to_string(), &request))
+                })
+                .transpose()?,
+            failed_devices,
+        })
     }
 
     /// Generate an "out-of-band" key query request for the given set of users.
@@ -477,29 +933,46 @@ impl OlmMachine {
 
     /// Decrypt an event from a room timeline.
     ///
+    /// With the `experimental-encrypted-state-events` feature, encrypted state
+    /// events (see `encryptStateEvent`) are decrypted too.
+    ///
+    /// If the event cannot be decrypted, the returned promise is rejected
+    /// with a `MegolmDecryptionError`: an `Error` named
+    /// `MegolmDecryptionError`, whose `code` property is a
+    /// `DecryptionErrorCode`, and that also has the `sessionId` and
+    /// `withheldCode` properties.
+    ///
     /// # Arguments
     ///
     /// * `event`, the event that should be decrypted.
     /// * `room_id`, the ID of the room where the event was sent to.
-    #[napi(strict)]
-    pub async fn decrypt_room_event(
+    /// * `decryption_settings`, the settings used to decrypt the event; by
+    ///   default, the sender's device doesn't need to be trusted.
+    #[napi(strict, ts_return_type = "Promise<DecryptedRoomEvent>")]
+    pub fn decrypt_room_event(
         &self,
+        env: Env,
         event: String,
         room_id: &identifiers::RoomId,
-    ) -> napi::Result<responses::DecryptedRoomEvent> {
+        decryption_settings: Option<&encryption::DecryptionSettings>,
+    ) -> napi::Result<JsObject> {
         let event = Raw::from_json(RawValue::from_string(event).map_err(into_err)?);
         let room_id = room_id.inner.clone();
-
-        let decryption_settings =
-            DecryptionSettings { sender_device_trust_requirement: TrustRequirement::Untrusted };
-
-        let room_event = self
-            .inner
-            .decrypt_room_event(&event, &room_id, &decryption_settings)
-            .await
-            .map_err(into_err)?;
-
-        Ok(room_event.into())
+        let decryption_settings = DecryptionSettings::from(decryption_settings);
+        let machine = self.inner.deref().clone();
+
+        env.execute_tokio_future(
+            async move {
+                Ok(machine
+                    .decrypt_room_event(&event, &room_id, &decryption_settings)
+                    .await
+                    .map_err(|error| errors::MegolmDecryptionError::new(&event, error)))
+            },
+            |env, room_event| match room_event {
+                Ok(room_event) => Ok(responses::DecryptedRoomEvent::from(room_event)),
+                Err(error) => Err(error.into_napi_error(env)),
+            },
+        )
     }
 
     /// Get the status of the private cross signing keys.
@@ -684,19 +1157,343 @@ impl OlmMachine {
         .map_err(into_err)
     }
 
+    /// Export the room keys we have, encrypted with the given passphrase.
+    ///
+    /// The export uses the standard format of the specification, i.e. an
+    /// armored string starting with `-----BEGIN MEGOLM SESSION DATA-----`,
+    /// which can be imported back with `import_room_keys`, or by any other
+    /// Matrix client.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_ids`, the IDs of the rooms for which the room keys should be
+    ///   exported; if not set, all the room keys are exported.
+    /// * `passphrase`, the passphrase that will be used to encrypt the exported
+    ///   room keys.
+    /// * `rounds`, the number of rounds that should be used for the key
+    ///   derivation when the passphrase gets turned into an AES key. More
+    ///   rounds are increasingly computationally intensive and as such help
+    ///   against brute-force attacks. Should be at least `10_000`, while values
+    ///   in the `100_000` ranges should be preferred.
+    #[napi]
+    pub async fn export_room_keys(
+        &self,
+        room_ids: Option<Vec<&identifiers::RoomId>>,
+        mut passphrase: String,
+        rounds: u32,
+    ) -> napi::Result<String> {
+        let room_ids = room_ids.map(|room_ids| {
+            room_ids.into_iter().map(|room_id| room_id.inner.clone()).collect::<Vec<_>>()
+        });
+
+        let exported_room_keys = self
+            .inner
+            .store()
+            .export_room_keys(|session| match &room_ids {
+                Some(room_ids) => room_ids.iter().any(|room_id| room_id == session.room_id()),
+                None => true,
+            })
+            .await
+            .map_err(into_err)?;
+
+        let encrypted =
+            matrix_sdk_crypto::encrypt_room_key_export(&exported_room_keys, &passphrase, rounds)
+                .map_err(into_err);
+
+        passphrase.zeroize();
+
+        encrypted
+    }
+
+    /// Import room keys that were exported with `export_room_keys`, or by any
+    /// other Matrix client.
+    ///
+    /// # Arguments
+    ///
+    /// * `exported_room_keys`, the armored export of the room keys, starting
+    ///   with `-----BEGIN MEGOLM SESSION DATA-----`.
+    /// * `passphrase`, the passphrase that was used to encrypt the exported
+    ///   room keys.
+    /// * `progress_listener`, an optional function that will be called with two
+    ///   arguments: the number of room keys processed so far, and the total
+    ///   number of room keys to import.
+    #[napi]
+    pub async fn import_room_keys(
+        &self,
+        exported_room_keys: String,
+        mut passphrase: String,
+        progress_listener: Option<ThreadsafeFunction<(u32, u32), ErrorStrategy::Fatal>>,
+    ) -> napi::Result<types::RoomKeyImportResult> {
+        let exported_room_keys =
+            matrix_sdk_crypto::decrypt_room_key_export(exported_room_keys.as_bytes(), &passphrase)
+                .map_err(into_err);
+
+        passphrase.zeroize();
+
+        let result = self
+            .inner
+            .store()
+            .import_exported_room_keys(exported_room_keys?, |progress, total| {
+                if let Some(progress_listener) = &progress_listener {
+                    progress_listener.call(
+                        (
+                            progress.try_into().unwrap_or(u32::MAX),
+                            total.try_into().unwrap_or(u32::MAX),
+                        ),
+                        ThreadsafeFunctionCallMode::NonBlocking,
+                    );
+                }
+            })
+            .await
+            .map_err(into_err)?;
+
+        Ok(result.into())
+    }
+
+    /// Import room keys from a server-side key backup into the store.
+    ///
+    /// The room keys are decrypted with the backup decryption key that was
+    /// saved with `save_backup_decryption_key`, and they are marked as already
+    /// backed up if `backup_version` is the version of the currently active
+    /// backup. Room keys that cannot be decrypted are skipped, and counted in
+    /// `RoomKeyImportResult.failedCount`.
+    ///
+    /// # Arguments
+    ///
+    /// * `backed_up_room_keys`, a JSON-encoded object of form `{ "<room_id>": {
+    ///   "<session_id>": <KeyBackupData> } }`, where `KeyBackupData` is the
+    ///   object returned by the `/room_keys/keys` endpoint.
+    /// * `backup_version`, the version of the backup the room keys come from.
+    /// * `progress_listener`, an optional function that will be called with two
+    ///   arguments: the number of room keys processed so far, and the total
+    ///   number of room keys to import.
+    #[napi]
+    pub async fn import_backed_up_room_keys(
+        &self,
+        backed_up_room_keys: String,
+        backup_version: String,
+        progress_listener: Option<ThreadsafeFunction<(u32, u32), ErrorStrategy::Fatal>>,
+    ) -> napi::Result<types::RoomKeyImportResult> {
+        let backed_up_room_keys: BTreeMap<OwnedRoomId, BTreeMap<String, KeyBackupData>> =
+            serde_json::from_str(&backed_up_room_keys).map_err(into_err)?;
+
+        let backup_machine = self.inner.backup_machine();
+        let backup_keys = backup_machine.get_backup_keys().await.map_err(into_err)?;
+        let decryption_key = backup_keys
+            .decryption_key
+            .ok_or_else(|| napi::Error::from_reason("No backup decryption key has been saved"))?;
+
+        let mut room_keys: BTreeMap<OwnedRoomId, BTreeMap<String, BackedUpRoomKey>> =
+            BTreeMap::new();
+        let mut failed_count = 0u32;
+
+        for (room_id, sessions) in backed_up_room_keys {
+            for (session_id, key_backup_data) in sessions {
+                let room_key = key_backup_data
+                    .session_data
+                    .deserialize()
+                    .ok()
+                    .and_then(|session_data| {
+                        decryption_key
+                            .decrypt_v1(
+                                &session_data.ephemeral.encode(),
+                                &session_data.mac.encode(),
+                                &session_data.ciphertext.encode(),
+                            )
+                            .ok()
+                    })
+                    .and_then(|decrypted| serde_json::from_str::<BackedUpRoomKey>(&decrypted).ok());
+
+                match room_key {
+                    Some(room_key) => {
+                        room_keys.entry(room_id.clone()).or_default().insert(session_id, room_key);
+                    }
+                    None => failed_count = failed_count.saturating_add(1),
+                }
+            }
+        }
+
+        let result = backup_machine
+            .import_backed_up_room_keys(room_keys, Some(&backup_version), |progress, total| {
+                if let Some(progress_listener) = &progress_listener {
+                    progress_listener.call(
+                        (
+                            progress.try_into().unwrap_or(u32::MAX),
+                            total.try_into().unwrap_or(u32::MAX),
+                        ),
+                        ThreadsafeFunctionCallMode::NonBlocking,
+                    );
+                }
+            })
+            .await
+            .map_err(into_err)?;
+
+        Ok(types::RoomKeyImportResult { failed_count, ..result.into() })
+    }
+
     /// Get the number of backed up room keys and the total number of room keys.
     #[napi]
     pub async fn room_key_counts(&self) -> napi::Result<RoomKeyCounts> {
         Ok(self.inner.backup_machine().room_key_counts().await.map_err(into_err)?.into())
     }
 
+    /// Share the history of a room with a user invited to it ([MSC4268]).
+    ///
+    /// It builds the bundle of the room keys of the room, encrypts it, and
+    /// passes the encrypted bundle to `upload`, which must upload it to the
+    /// media repository and resolve to its MXC URI. The bundle is then shared
+    /// with all the devices of the invitee.
+    ///
+    /// Returns the to-device requests, sending an encrypted `m.room_key_bundle`
+    /// event pointing to the uploaded bundle, that need to be sent out; or an
+    /// empty array, without calling `upload`, if there is no room key to
+    /// share. An Olm session must already exist with the devices of the
+    /// invitee, see `getMissingSessions`.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room whose history should be shared.
+    /// * `invitee`, the ID of the user invited to the room.
+    /// * `upload`, a function called with the encrypted bundle, as an
+    ///   `Uint8Array`, returning a promise resolving to the MXC URI it has been
+    ///   uploaded to.
+    /// * `share_strategy`, the strategy used to decide which devices of the
+    ///   invitee receive the bundle, `CollectStrategy.AllDevices` by default.
+    ///
+    /// [MSC4268]: https://github.com/matrix-org/matrix-spec-proposals/pull/4268
+    #[napi]
+    pub async fn share_room_key_bundle(
+        &self,
+        room_id: &identifiers::RoomId,
+        invitee: &identifiers::UserId,
+        #[napi(ts_arg_type = "(encryptedBundle: Uint8Array) => Promise<string>")]
+        upload: ThreadsafeFunction<Uint8Array, ErrorStrategy::Fatal>,
+        share_strategy: Option<encryption::CollectStrategy>,
+    ) -> napi::Result<Vec<requests::ToDeviceRequest>> {
+        let room_id = room_id.inner.clone();
+
+        let bundle = self.inner.store().build_room_key_bundle(&room_id).await.map_err(into_err)?;
+
+        if bundle.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let bundle = serde_json::to_vec(&bundle).map_err(into_err)?;
+        let (encrypted_bundle, media_encryption_info) = attachment::encrypt(&bundle)?;
+
+        let url: Promise<String> = upload.call_async(Uint8Array::new(encrypted_bundle)).await?;
+
+        // An encrypted file is made of the media encryption info and the URL.
+        let mut file = serde_json::to_value(media_encryption_info).map_err(into_err)?;
+        file["url"] = url.await?.into();
+
+        let bundle_data =
+            RoomKeyBundleContent { room_id, file: serde_json::from_value(file).map_err(into_err)? };
+        let share_strategy =
+            share_strategy.unwrap_or(encryption::CollectStrategy::AllDevices).into();
+
+        self.inner
+            .share_room_key_bundle_data(&invitee.inner, &share_strategy, bundle_data)
+            .await
+            .map_err(into_err)?
+            .iter()
+            .map(requests::ToDeviceRequest::try_from)
+            .collect()
+    }
+
+    /// Import the room keys of a room key bundle ([MSC4268]), shared by the
+    /// user who invited us to the room.
+    ///
+    /// The `m.room_key_bundle` to-device event sent by the inviter must have
+    /// been received through `receiveSyncChanges` beforehand. It contains
+    /// the MXC URI of the encrypted bundle, which must be downloaded and
+    /// passed as `bundle_data`. If several users shared a bundle for the
+    /// room, the last one received is imported.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room whose history is shared.
+    /// * `bundle_data`, the encrypted bundle, as downloaded from the media
+    ///   repository.
+    /// * `progress_listener`, an optional function that will be called with two
+    ///   arguments: the number of room keys processed so far, and the total
+    ///   number of room keys to import.
+    ///
+    /// [MSC4268]: https://github.com/matrix-org/matrix-spec-proposals/pull/4268
+    #[napi]
+    pub async fn receive_room_key_bundle(
+        &self,
+        room_id: &identifiers::RoomId,
+        bundle_data: Uint8Array,
+        progress_listener: Option<ThreadsafeFunction<(u32, u32), ErrorStrategy::Fatal>>,
+    ) -> napi::Result<()> {
+        let store = self.inner.store();
+        let not_received = || {
+            napi::Error::from_reason(format!(
+                "No room key bundle has been received for {}",
+                room_id.inner
+            ))
+        };
+
+        let Some(inviter) = store
+            .get_custom_value(&room_key_bundle_sender_key(&room_id.inner))
+            .await
+            .map_err(into_err)?
+        else {
+            return Err(not_received());
+        };
+        let inviter = OwnedUserId::try_from(String::from_utf8(inviter).map_err(into_err)?)
+            .map_err(into_err)?;
+
+        let Some(bundle_info) = store
+            .get_received_room_key_bundle_data(&room_id.inner, &inviter)
+            .await
+            .map_err(into_err)?
+        else {
+            return Err(not_received());
+        };
+
+        // The media encryption info are the encrypted file without its URL.
+        let media_encryption_info =
+            serde_json::to_value(&bundle_info.bundle_data.file).map_err(into_err)?;
+        let bundle = attachment::decrypt(
+            bundle_data.deref(),
+            serde_json::from_value(media_encryption_info).map_err(into_err)?,
+        )?;
+        let bundle = serde_json::from_slice(&bundle).map_err(into_err)?;
+
+        store
+            .receive_room_key_bundle(&bundle_info, bundle, |progress, total| {
+                if let Some(progress_listener) = &progress_listener {
+                    progress_listener.call(
+                        (
+                            progress.try_into().unwrap_or(u32::MAX),
+                            total.try_into().unwrap_or(u32::MAX),
+                        ),
+                        ThreadsafeFunctionCallMode::NonBlocking,
+                    );
+                }
+            })
+            .await
+            .map_err(into_err)
+    }
+
+    /// Get the methods to create and rehydrate dehydrated devices
+    /// ([MSC3814]).
+    ///
+    /// [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+    #[napi]
+    pub fn dehydrated_devices(&self) -> dehydrated_devices::DehydratedDevices {
+        self.inner.dehydrated_devices().into()
+    }
+
     /// Shut down the `OlmMachine`.
     ///
     /// The `OlmMachine` cannot be used after this method has been called,
     /// otherwise it will panic.
     ///
     /// All associated resources will be closed too, like the crypto storage
-    /// connections.
+    /// connections, and the registered callbacks won't be called anymore.
     ///
     /// # Safety
     ///
@@ -704,7 +1501,232 @@ impl OlmMachine {
     /// `OlmMachine` after this `close` method has been called.
     #[napi(strict)]
     pub fn close(&mut self) {
+        self.callback_tasks = CallbackTasks::default();
         self.inner = OlmMachineInner::Closed;
+        self.memory_store = None;
+    }
+
+    /// Serialize the whole state of the machine to a blob, encrypted
+    /// with the given passphrase.
+    ///
+    /// The machine must have been created with `StoreType.Memory`, or
+    /// with `OlmMachine.initializeFromSnapshot`. It can be restored
+    /// with `OlmMachine.initializeFromSnapshot`.
+    #[napi(strict)]
+    pub fn snapshot(&self, mut passphrase: String) -> napi::Result<Uint8Array> {
+        let snapshot =
+            self.memory_store.as_ref().map(|store| store.snapshot(&passphrase)).transpose();
+        passphrase.zeroize();
+
+        snapshot?.flatten().map(Uint8Array::new).ok_or_else(|| {
+            napi::Error::from_reason(
+                "Only an `OlmMachine` using `StoreType.Memory` can be snapshotted",
+            )
+        })
+    }
+
+    /// Register a callback which will be called whenever there is an update to
+    /// a room key.
+    ///
+    /// The callback is called with an array of `RoomKeyInfo`, whenever room
+    /// keys are received or imported, be it from a to-device event, a key
+    /// backup, a key export or a secret gossip.
+    ///
+    /// Registering a new callback replaces the previous one.
+    #[napi]
+    pub fn register_room_key_updated_callback(
+        &self,
+        env: Env,
+        mut callback: ThreadsafeFunction<Vec<sync_events::RoomKeyInfo>, ErrorStrategy::Fatal>,
+    ) -> napi::Result<()> {
+        // Don't prevent Node.js from exiting because of the callback.
+        callback.unref(&env)?;
+
+        let mut stream = Box::pin(self.inner.store().room_keys_received_stream());
+
+        let task = spawn(async move {
+            while let Some(room_key_infos) = stream.next().await {
+                // Updates are missed if the callback lags too much behind, there is nothing
+                // we can do about them.
+                let Ok(room_key_infos) = room_key_infos else {
+                    continue;
+                };
+
+                callback.call(
+                    room_key_infos.into_iter().map(Into::into).collect(),
+                    ThreadsafeFunctionCallMode::NonBlocking,
+                );
+            }
+        });
+
+        self.callback_tasks.replace(CallbackKind::RoomKeyUpdated, task);
+
+        Ok(())
+    }
+
+    /// Register a callback which will be called whenever we receive a
+    /// notification that some room keys have been withheld.
+    ///
+    /// The callback is called with an array of `RoomKeyWithheldInfo`.
+    ///
+    /// Registering a new callback replaces the previous one.
+    #[napi]
+    pub fn register_room_keys_withheld_callback(
+        &self,
+        env: Env,
+        mut callback: ThreadsafeFunction<
+            Vec<sync_events::RoomKeyWithheldInfo>,
+            ErrorStrategy::Fatal,
+        >,
+    ) -> napi::Result<()> {
+        // Don't prevent Node.js from exiting because of the callback.
+        callback.unref(&env)?;
+
+        let mut stream = Box::pin(self.inner.store().room_keys_withheld_received_stream());
+
+        let task = spawn(async move {
+            while let Some(withheld_infos) = stream.next().await {
+                callback.call(
+                    withheld_infos.into_iter().map(Into::into).collect(),
+                    ThreadsafeFunctionCallMode::NonBlocking,
+                );
+            }
+        });
+
+        self.callback_tasks.replace(CallbackKind::RoomKeysWithheld, task);
+
+        Ok(())
+    }
+
+    /// Register a callback which will be called whenever the identity of a
+    /// user we track is created or updated.
+    ///
+    /// The callback is called with an array of `UserId`, whenever a response to
+    /// a `/keys/query` request is received through `markRequestAsSent` and
+    /// some user identities are new or have changed, e.g. because a user
+    /// reset their master key.
+    ///
+    /// Registering a new callback replaces the previous one.
+    #[napi]
+    pub fn register_user_identity_updated_callback(
+        &self,
+        env: Env,
+        mut callback: ThreadsafeFunction<Vec<identifiers::UserId>, ErrorStrategy::Fatal>,
+    ) -> napi::Result<()> {
+        // Don't prevent Node.js from exiting because of the callback.
+        callback.unref(&env)?;
+
+        let mut stream = Box::pin(self.inner.store().user_identities_stream());
+
+        let task = spawn(async move {
+            while let Some(updates) = stream.next().await {
+                let user_ids: Vec<identifiers::UserId> = updates
+                    .new
+                    .into_keys()
+                    .chain(updates.changed.into_keys())
+                    .map(Into::into)
+                    .collect();
+
+                if !user_ids.is_empty() {
+                    callback.call(user_ids, ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            }
+        });
+
+        self.callback_tasks.replace(CallbackKind::UserIdentityUpdated, task);
+
+        Ok(())
+    }
+
+    /// Register a callback which will be called whenever the devices of a user
+    /// we track are created or updated.
+    ///
+    /// The callback is called with an array of `UserId`, whenever a response to
+    /// a `/keys/query` request is received through `markRequestAsSent` and
+    /// some of their devices are new or have changed. Each user appears only
+    /// once.
+    ///
+    /// Registering a new callback replaces the previous one.
+    #[napi]
+    pub fn register_devices_updated_callback(
+        &self,
+        env: Env,
+        mut callback: ThreadsafeFunction<Vec<identifiers::UserId>, ErrorStrategy::Fatal>,
+    ) -> napi::Result<()> {
+        // Don't prevent Node.js from exiting because of the callback.
+        callback.unref(&env)?;
+
+        let mut stream = Box::pin(self.inner.store().devices_stream());
+
+        let task = spawn(async move {
+            while let Some(updates) = stream.next().await {
+                let user_ids: BTreeSet<_> =
+                    updates.new.into_keys().chain(updates.changed.into_keys()).collect();
+
+                if !user_ids.is_empty() {
+                    callback.call(
+                        user_ids.into_iter().map(Into::into).collect(),
+                        ThreadsafeFunctionCallMode::NonBlocking,
+                    );
+                }
+            }
+        });
+
+        self.callback_tasks.replace(CallbackKind::DevicesUpdated, task);
+
+        Ok(())
+    }
+
+    /// Request the secrets we are missing, i.e. the private cross-signing
+    /// keys and the backup decryption key, from our other devices.
+    ///
+    /// The `m.secret.request` to-device requests are returned by the next
+    /// call to `outgoingRequests`. The `m.secret.send` responses of our
+    /// verified devices are handled by `receiveSyncChanges`: the
+    /// cross-signing keys are imported automatically, the other secrets are
+    /// kept in the secret inbox, see `getSecretsFromInbox`.
+    ///
+    /// Returns `true` if some secrets were missing and have been requested.
+    #[napi]
+    pub async fn request_missing_secrets(&self) -> napi::Result<bool> {
+        self.inner.query_missing_secrets_from_other_sessions().await.map_err(into_err)
+    }
+
+    /// Get the secrets with the given name we have received from our other
+    /// devices, e.g. `m.megolm_backup.v1` for the backup decryption key.
+    ///
+    /// The secrets should be checked (e.g. that a backup decryption key
+    /// matches the current backup) and saved where they belong, then
+    /// removed from the inbox with `deleteSecretsFromInbox`.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name`, the name of the secrets.
+    #[napi]
+    pub async fn get_secrets_from_inbox(&self, secret_name: String) -> napi::Result<Vec<String>> {
+        let secret_name = SecretName::from(secret_name);
+
+        Ok(self
+            .inner
+            .store()
+            .get_secrets_from_inbox(&secret_name)
+            .await
+            .map_err(into_err)?
+            .into_iter()
+            .map(|gossipped_secret| gossipped_secret.event.content.secret.clone())
+            .collect())
+    }
+
+    /// Delete the secrets with the given name from the secret inbox.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name`, the name of the secrets.
+    #[napi]
+    pub async fn delete_secrets_from_inbox(&self, secret_name: String) -> napi::Result<()> {
+        let secret_name = SecretName::from(secret_name);
+
+        self.inner.store().delete_secrets_from_inbox(&secret_name).await.map_err(into_err)
     }
 
     /// Export the client's secrets to store in Secret Storage, encrypted using
@@ -712,7 +1734,8 @@ impl OlmMachine {
     ///
     /// Returns the items to store in account data.
     ///
-    /// Currently only exports the cross-signing keys.
+    /// Exports the cross-signing keys, and the backup decryption key if we
+    /// have one.
     #[napi]
     pub async fn export_secrets_for_secret_storage(
         &self,
@@ -729,20 +1752,158 @@ impl OlmMachine {
             bundle.cross_signing.self_signing_key.clone(),
             &SecretName::CrossSigningSelfSigningKey,
         )?;
-        Ok(SecretStorageItems { master_key, user_signing_key, self_signing_key })
+        let backup_key = bundle
+            .backup
+            .map(|backup| match backup {
+                BackupSecrets::MegolmBackupV1Curve25519AesSha2(backup) => secret_storage_key
+                    .encrypt(backup.key.to_base64().into(), &SecretName::RecoveryKey),
+            })
+            .transpose()?;
+        Ok(SecretStorageItems { master_key, user_signing_key, self_signing_key, backup_key })
+    }
+
+    /// Set up Secret Storage from scratch.
+    ///
+    /// It creates a new secret storage key and a new backup decryption key,
+    /// then encrypts these secrets and the cross-signing keys with the secret
+    /// storage key. The existing cross-signing keys are reused, unless
+    /// `reset` is set: resetting them replaces the user's identity, and
+    /// throws away all the verifications.
+    ///
+    /// The new backup decryption key is saved in the crypto store, without a
+    /// backup version, which must be saved with `saveBackupDecryptionKey` once
+    /// the backup version has been created.
+    ///
+    /// Returns a `SecretStorageBootstrapResult`, holding the account data
+    /// events to upload, the requests to upload the cross-signing keys, and
+    /// the body of the request to create the backup version.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase`, the passphrase to derive the secret storage key from; if
+    ///   not set, a random key is created.
+    /// * `reset`, whether new cross-signing keys should be created even if
+    ///   there are existing ones, see `bootstrapCrossSigning`; `false` by
+    ///   default.
+    #[napi]
+    pub async fn bootstrap_secret_storage(
+        &self,
+        passphrase: Option<String>,
+        reset: Option<bool>,
+    ) -> napi::Result<SecretStorageBootstrapResult> {
+        let secret_storage_key = match passphrase {
+            Some(mut passphrase) => {
+                let secret_storage_key = SecretStorageKey {
+                    inner: matrix_sdk_crypto::secret_storage::SecretStorageKey::new_from_passphrase(
+                        &passphrase,
+                    ),
+                };
+                passphrase.zeroize();
+
+                secret_storage_key
+            }
+            None => SecretStorageKey::create_random_key(),
+        };
+
+        let cross_signing_bootstrap_requests =
+            self.bootstrap_cross_signing(reset.unwrap_or(false)).await?;
+        let items = self.export_secrets_for_secret_storage(&secret_storage_key).await?;
+
+        let backup_decryption_key = matrix_sdk_crypto::store::types::BackupDecryptionKey::new();
+        let mut backup_info = backup_decryption_key.to_backup_info();
+        let backup_machine = self.inner.backup_machine();
+        backup_machine.sign_backup(&mut backup_info).await.map_err(into_err)?;
+
+        // The backup version is only known once the server has created it, so the
+        // key is saved without one for now.
+        backup_machine
+            .save_decryption_key(Some(backup_decryption_key.clone()), None)
+            .await
+            .map_err(into_err)?;
+
+        let backup_key = secret_storage_key
+            .encrypt(backup_decryption_key.to_base64().into(), &SecretName::RecoveryKey)?;
+
+        let key_id = secret_storage_key.key_id();
+        let account_data = [
+            (secret_storage_key.event_type(), secret_storage_key.account_data_content()?),
+            (
+                "m.secret_storage.default_key".to_owned(),
+                serde_json::json!({ "key": key_id }).to_string(),
+            ),
+            (SecretName::CrossSigningMasterKey.to_string(), items.master_key),
+            (SecretName::CrossSigningSelfSigningKey.to_string(), items.self_signing_key),
+            (SecretName::CrossSigningUserSigningKey.to_string(), items.user_signing_key),
+            (SecretName::RecoveryKey.to_string(), backup_key),
+        ]
+        .into_iter()
+        .map(|(event_type, content)| AccountDataEvent { event_type, content })
+        .collect();
+
+        Ok(SecretStorageBootstrapResult {
+            secret_storage_key: Some(secret_storage_key),
+            secret_storage_key_reference: None,
+            account_data,
+            cross_signing_bootstrap_requests,
+            backup_decryption_key,
+            backup_version_request: serde_json::to_string(&backup_info).map_err(into_err)?,
+        })
     }
 
     /// Import secrets from Secret Storage, and sign the device's key with the
     /// user's self-signing key.
     ///
+    /// If the items contain a backup decryption key, it is saved in the crypto
+    /// store too, see `saveBackupDecryptionKey`. If `backup_info` is given,
+    /// nothing is imported unless the key matches the public key of the
+    /// backup; otherwise, the caller is responsible for checking it, e.g. by
+    /// comparing `BackupDecryptionKey.megolmV1PublicKey` with the backup's
+    /// `auth_data.public_key`.
+    ///
     /// Returns a signature upload request to upload the signature to the
     /// server.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_storage_key`, the key the secrets are encrypted with.
+    /// * `items`, the secrets, as read from the account data.
+    /// * `backup_version`, the version of the backup the backup decryption key
+    ///   belongs to; by default, the version of the backup we already know of,
+    ///   if any.
+    /// * `backup_info`, the stringified JSON object describing the backup the
+    ///   backup decryption key belongs to, with the `algorithm` and `auth_data`
+    ///   fields, as for `verifyBackup`.
     #[napi]
     pub async fn import_secrets_from_secret_storage(
         &self,
         secret_storage_key: &SecretStorageKey,
         items: &SecretStorageItems,
+        backup_version: Option<String>,
+        backup_info: Option<String>,
     ) -> napi::Result<requests::SignatureUploadRequest> {
+        let backup_key = items
+            .backup_key
+            .as_ref()
+            .map(|backup_key| -> napi::Result<_> {
+                let backup_key =
+                    secret_storage_key.decrypt(backup_key, &SecretName::RecoveryKey)?;
+
+                matrix_sdk_crypto::store::types::BackupDecryptionKey::from_base64(&backup_key)
+                    .map_err(into_err)
+            })
+            .transpose()?;
+
+        if let (Some(backup_key), Some(backup_info)) = (&backup_key, backup_info) {
+            let backup_info: RoomKeyBackupInfo =
+                serde_json::from_str(backup_info.as_str()).map_err(into_err)?;
+
+            if !backup_key.backup_key_matches(&backup_info) {
+                return Err(napi::Error::from_reason(
+                    "The backup decryption key doesn't match the public key of the backup",
+                ));
+            }
+        }
+
         let master_key =
             secret_storage_key.decrypt(&items.master_key, &SecretName::CrossSigningMasterKey)?;
         let self_signing_key = secret_storage_key
@@ -766,6 +1927,19 @@ impl OlmMachine {
             return Err(napi::Error::from_reason("failed to import the keys"));
         }
 
+        if let Some(backup_key) = backup_key {
+            let backup_machine = self.inner.backup_machine();
+            let backup_version = match backup_version {
+                Some(backup_version) => Some(backup_version),
+                None => backup_machine.get_backup_keys().await.map_err(into_err)?.backup_version,
+            };
+
+            backup_machine
+                .save_decryption_key(Some(backup_key), backup_version)
+                .await
+                .map_err(into_err)?;
+        }
+
         // self-sign, and return the signature request
         let device = self
             .inner
@@ -793,4 +1967,207 @@ impl OlmMachine {
             .map_err(into_err)?;
         Ok(device.map(|device| device.into()))
     }
+
+    /// Get all the devices of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id`, the unique ID of the user that the devices belong to.
+    /// * `timeout`, the number of seconds to wait for a pending `/keys/query`
+    ///   request for this user to complete, if any.
+    #[napi]
+    pub async fn get_user_devices(
+        &self,
+        user_id: &identifiers::UserId,
+        timeout: Option<f64>,
+    ) -> napi::Result<UserDevices> {
+        let devices = self
+            .inner
+            .get_user_devices(&user_id.inner, timeout.map(Duration::from_secs_f64))
+            .await
+            .map_err(into_err)?;
+        Ok(devices.into())
+    }
+
+    /// Get the cross signing user identity of a user.
+    ///
+    /// Returns an `OwnUserIdentity` if the user is our own user, an
+    /// `OtherUserIdentity` otherwise, or `null` if no identity is known for
+    /// this user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id`, the unique ID of the user that the identity belongs to.
+    /// * `timeout`, the number of seconds to wait for a pending `/keys/query`
+    ///   request for this user to complete, if any.
+    #[napi]
+    pub async fn get_identity(
+        &self,
+        user_id: &identifiers::UserId,
+        timeout: Option<f64>,
+    ) -> napi::Result<Option<Either<identities::OwnUserIdentity, identities::OtherUserIdentity>>>
+    {
+        let identity = self
+            .inner
+            .get_identity(&user_id.inner, timeout.map(Duration::from_secs_f64))
+            .await
+            .map_err(into_err)?;
+
+        Ok(identity.map(|identity| match identity {
+            matrix_sdk_crypto::UserIdentity::Own(identity) => Either::A(identity.into()),
+            matrix_sdk_crypto::UserIdentity::Other(identity) => Either::B(identity.into()),
+        }))
+    }
+
+    /// Request an interactive verification with the given device.
+    ///
+    /// Returns a `RequestVerificationResult`, holding the new
+    /// `VerificationRequest` and the to-device request that needs to
+    /// be sent out to notify the other device.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id`, the ID of the user that owns the device.
+    /// * `device_id`, the ID of the device to verify.
+    /// * `methods`, the verification methods we want to advertise; if not set,
+    ///   the default methods are used.
+    #[napi(strict)]
+    pub async fn request_verification(
+        &self,
+        user_id: &identifiers::UserId,
+        device_id: &identifiers::DeviceId,
+        methods: Option<Vec<verification::VerificationMethod>>,
+    ) -> napi::Result<verification::RequestVerificationResult> {
+        let device = self
+            .inner
+            .get_device(&user_id.inner, &device_id.inner, None)
+            .await
+            .map_err(into_err)?
+            .ok_or_else(|| {
+                napi::Error::from_reason(format!(
+                    "Unknown device `{}` of user `{}`",
+                    device_id.inner, user_id.inner
+                ))
+            })?;
+
+        match methods {
+            Some(methods) => {
+                device
+                    .request_verification_with_methods(
+                        methods.into_iter().map(Into::into).collect(),
+                    )
+                    .await
+            }
+            None => device.request_verification().await,
+        }
+        .try_into()
+    }
+
+    /// Get a verification request object with the given flow ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id`, the ID of the user the verification request is with.
+    /// * `flow_id`, the unique ID of the verification flow.
+    #[napi(strict)]
+    pub fn get_verification_request(
+        &self,
+        user_id: &identifiers::UserId,
+        flow_id: String,
+    ) -> Option<verification::VerificationRequest> {
+        self.inner.get_verification_request(&user_id.inner, flow_id).map(Into::into)
+    }
+
+    /// Get all the verification requests of a given user.
+    #[napi(strict)]
+    pub fn get_verification_requests(
+        &self,
+        user_id: &identifiers::UserId,
+    ) -> Vec<verification::VerificationRequest> {
+        self.inner.get_verification_requests(&user_id.inner).into_iter().map(Into::into).collect()
+    }
+
+    /// Get a SAS verification object with the given flow ID.
+    ///
+    /// Returns `null` if no such verification exists, or if the
+    /// verification isn't a SAS verification.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id`, the ID of the user the verification is with.
+    /// * `flow_id`, the unique ID of the verification flow.
+    #[napi(strict)]
+    pub fn get_sas(
+        &self,
+        user_id: &identifiers::UserId,
+        flow_id: String,
+    ) -> Option<verification::Sas> {
+        self.inner.get_verification(&user_id.inner, &flow_id)?.sas_v1().map(Into::into)
+    }
+}
+
+#[cfg(feature = "experimental-encrypted-state-events")]
+#[napi]
+impl OlmMachine {
+    /// Encrypt a JSON-encoded state event content for the given room, as
+    /// specified by [MSC3414].
+    ///
+    /// The event type and the state key are bound into the ciphertext, the
+    /// encrypted event must be sent as an `m.room.encrypted` state event
+    /// whose state key is `{event_type}:{state_key}`. Such events are
+    /// decrypted by `decryptRoomEvent`, which checks that binding.
+    ///
+    /// Returns the JSON-encoded content of the `m.room.encrypted` event.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id`, the ID of the room for which the state event should be
+    ///   encrypted.
+    /// * `event_type`, the plaintext type of the state event.
+    /// * `state_key`, the plaintext state key of the state event.
+    /// * `content`, the JSON-encoded content of the state event that should be
+    ///   encrypted.
+    ///
+    /// [MSC3414]: https://github.com/matrix-org/matrix-spec-proposals/pull/3414
+    #[napi(strict)]
+    pub async fn encrypt_state_event(
+        &self,
+        room_id: &identifiers::RoomId,
+        event_type: String,
+        state_key: String,
+        content: String,
+    ) -> napi::Result<String> {
+        let room_id = room_id.inner.clone();
+        let content = serde_json::from_str(content.as_str()).map_err(into_err)?;
+
+        let encrypted = self
+            .inner
+            .encrypt_state_event_raw(&room_id, event_type.as_ref(), state_key.as_ref(), &content)
+            .await
+            .map_err(into_err)?;
+
+        serde_json::to_string(&encrypted).map_err(into_err)
+    }
+}
+
+#[cfg(feature = "qrcode")]
+#[napi]
+impl OlmMachine {
+    /// Get a QR code verification object with the given flow ID.
+    ///
+    /// Returns `null` if no such verification exists, or if the
+    /// verification isn't a QR code verification.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id`, the ID of the user the verification is with.
+    /// * `flow_id`, the unique ID of the verification flow.
+    #[napi(strict)]
+    pub fn get_qr(
+        &self,
+        user_id: &identifiers::UserId,
+        flow_id: String,
+    ) -> Option<verification::Qr> {
+        self.inner.get_verification(&user_id.inner, &flow_id)?.qr_v1().map(Into::into)
+    }
 }
@@ -1,7 +1,11 @@
 //! Olm types.
 
+use matrix_sdk_common::ruma;
+use matrix_sdk_crypto::olm::{PickledOutboundGroupSession, ShareInfo};
 use napi_derive::*;
 
+use crate::identifiers;
+
 /// Struct representing the state of our private cross signing keys,
 /// it shows which private cross signing keys we have locally stored.
 #[napi]
@@ -38,3 +42,98 @@ impl CrossSigningStatus {
         self.inner.has_user_signing
     }
 }
+
+/// Information about the outbound group session currently used to encrypt
+/// the events of a room, see `OlmMachine.getOutboundGroupSessionInfo`.
+#[napi]
+pub struct OutboundGroupSessionInfo {
+    /// The ID of the session.
+    #[napi(readonly)]
+    pub session_id: String,
+
+    /// The ID of the room the session is used in.
+    #[napi(readonly)]
+    pub room_id: identifiers::RoomId,
+
+    /// Timestamp representing the time the session has been created at, in
+    /// milliseconds since the Unix epoch.
+    #[napi(readonly)]
+    pub creation_time: f64,
+
+    /// The number of messages the session has encrypted.
+    #[napi(readonly)]
+    pub message_count: f64,
+
+    /// Whether the session has been shared with the room members.
+    #[napi(readonly)]
+    pub shared: bool,
+
+    /// Whether the session has been invalidated, and will be replaced by a
+    /// new one the next time a room key is shared.
+    #[napi(readonly)]
+    pub invalidated: bool,
+
+    /// The devices the session has been shared with.
+    #[napi(readonly)]
+    pub shared_with_devices: Vec<SharedWithDevice>,
+}
+
+impl OutboundGroupSessionInfo {
+    pub(crate) fn new(session_id: String, pickle: PickledOutboundGroupSession) -> Self {
+        let shared_with_devices = pickle
+            .shared_with_set
+            .iter()
+            .flat_map(|(user_id, devices)| {
+                devices.iter().filter_map(move |(device_id, share_info)| match share_info {
+                    ShareInfo::Shared(shared_with) => Some(SharedWithDevice {
+                        user_id: user_id.clone(),
+                        device_id: device_id.clone(),
+                        message_index: shared_with.message_index,
+                    }),
+                    ShareInfo::Withheld(_) => None,
+                })
+            })
+            .collect();
+
+        Self {
+            session_id,
+            room_id: pickle.room_id.into(),
+            creation_time: u64::from(pickle.creation_time.0) as f64 * 1000.0,
+            message_count: pickle.message_count as f64,
+            shared: pickle.shared,
+            invalidated: pickle.invalidated,
+            shared_with_devices,
+        }
+    }
+}
+
+/// A device an outbound group session has been shared with.
+#[napi]
+#[derive(Clone)]
+pub struct SharedWithDevice {
+    user_id: ruma::OwnedUserId,
+    device_id: ruma::OwnedDeviceId,
+    message_index: u32,
+}
+
+#[napi]
+impl SharedWithDevice {
+    /// The user ID of the device's owner.
+    #[napi(getter)]
+    pub fn user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.user_id.clone())
+    }
+
+    /// The device ID.
+    #[napi(getter)]
+    pub fn device_id(&self) -> identifiers::DeviceId {
+        identifiers::DeviceId::from(self.device_id.clone())
+    }
+
+    /// The message index of the session at the time it has been shared with
+    /// the device.
+    #[napi(getter)]
+    pub fn message_index(&self) -> u32 {
+        self.message_index
+    }
+}
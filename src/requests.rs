@@ -3,10 +3,13 @@
 use std::time::Duration;
 
 use matrix_sdk_common::ruma::{
-    api::client::keys::{
-        claim_keys::v3::Request as RumaKeysClaimRequest,
-        upload_keys::v3::Request as RumaKeysUploadRequest,
-        upload_signatures::v3::Request as RumaSignatureUploadRequest,
+    api::client::{
+        dehydrated_device::put_dehydrated_device::unstable::Request as RumaPutDehydratedDeviceRequest,
+        keys::{
+            claim_keys::v3::Request as RumaKeysClaimRequest,
+            upload_keys::v3::Request as RumaKeysUploadRequest,
+            upload_signatures::v3::Request as RumaSignatureUploadRequest,
+        },
     },
     events::MessageLikeEventContent,
 };
@@ -89,6 +92,7 @@ impl KeysQueryRequest {
 ///
 /// [specification]: https://spec.matrix.org/unstable/client-server-api/#post_matrixclientv3keysclaim
 #[napi]
+#[derive(Clone)]
 pub struct KeysClaimRequest {
     /// The request ID.
     #[napi(readonly)]
@@ -119,6 +123,7 @@ impl KeysClaimRequest {
 ///
 /// [specification]: https://spec.matrix.org/unstable/client-server-api/#put_matrixclientv3sendtodeviceeventtypetxnid
 #[napi]
+#[derive(Clone)]
 pub struct ToDeviceRequest {
     /// The request ID.
     #[napi(readonly)]
@@ -183,6 +188,7 @@ impl SignatureUploadRequest {
 ///
 /// [specification]: https://spec.matrix.org/unstable/client-server-api/#put_matrixclientv3roomsroomidsendeventtypetxnid
 #[napi]
+#[derive(Clone)]
 pub struct RoomMessageRequest {
     /// The request ID.
     #[napi(readonly)]
@@ -244,6 +250,28 @@ impl KeysBackupRequest {
     }
 }
 
+/// Data for a request to the `PUT /dehydrated_device` API endpoint
+/// ([MSC3814]).
+///
+/// Uploads a dehydrated device. This request doesn't need to be marked as
+/// sent.
+///
+/// [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+#[napi]
+pub struct PutDehydratedDeviceRequest {
+    /// The request ID, always empty.
+    #[napi(readonly)]
+    pub id: String,
+
+    /// A JSON-encoded string containing the rest of the payload: `device_id`,
+    /// `initial_device_display_name`, `device_data`, `device_keys`,
+    /// `one_time_keys` and `fallback_keys`.
+    ///
+    /// It represents the body of the HTTP request.
+    #[napi(readonly)]
+    pub body: String,
+}
+
 macro_rules! request {
     (
         $destination_request:ident from $source_request:ident
@@ -351,6 +379,7 @@ request!(ToDeviceRequest from RumaToDeviceRequest extracts event_type: string, t
 request!(SignatureUploadRequest from RumaSignatureUploadRequest groups signed_keys);
 request!(RoomMessageRequest from RumaRoomMessageRequest extracts room_id: string, txn_id: string, event_type: event_type, content: json);
 request!(KeysBackupRequest from RumaKeysBackupRequest groups rooms);
+request!(PutDehydratedDeviceRequest from RumaPutDehydratedDeviceRequest groups device_id, initial_device_display_name "optional", device_data, device_keys, one_time_keys, fallback_keys);
 
 pub type OutgoingRequests = Either6<
     KeysUploadRequest,
@@ -423,6 +452,7 @@ pub enum RequestType {
 }
 
 #[napi]
+#[derive(Clone)]
 /// The requests needed to upload the cross-signing data to the server
 pub struct CrossSigningBootstrapRequests {
     /// The request to upload the device's keys.
@@ -13,10 +13,11 @@ use matrix_sdk_common::ruma::{
     },
     serde::Raw,
 };
-use matrix_sdk_crypto::secret_storage;
+use matrix_sdk_crypto::{secret_storage, store};
+use napi::{bindgen_prelude::Reference, Env};
 use napi_derive::napi;
 
-use crate::into_err;
+use crate::{backup::BackupDecryptionKey, into_err, requests::CrossSigningBootstrapRequests};
 
 /// A key for encrypting/decrypting data in secret storage
 #[napi]
@@ -92,13 +93,20 @@ impl SecretStorageKey {
         plaintext: String,
         secret_name: &SecretName,
     ) -> napi::Result<String> {
+        let mut encrypted = BTreeMap::new();
+        encrypted.insert(self.key_id(), self.encrypted_data(plaintext, secret_name));
+        serde_json::to_string(&SecretEventContent::new(encrypted)).map_err(into_err)
+    }
+
+    /// Encrypt a secret string, returning the data to store under the key ID
+    /// of this [`SecretStorageKey`] in the `encrypted` property of the
+    /// account data content.
+    fn encrypted_data(&self, plaintext: String, secret_name: &SecretName) -> SecretEncryptedData {
         let plaintext_string = plaintext.into_bytes();
         let encrypted_data = self.inner.encrypt(plaintext_string, secret_name);
         let encrypted_data =
             Raw::new(&encrypted_data).expect("We should be able to serialize our encrypted data");
-        let mut encrypted = BTreeMap::new();
-        encrypted.insert(self.key_id(), SecretEncryptedData::new(encrypted_data));
-        serde_json::to_string(&SecretEventContent::new(encrypted)).map_err(into_err)
+        SecretEncryptedData::new(encrypted_data)
     }
 
     /// Decrypt the given Secret Storage item, given as the JSON-encoded
@@ -122,11 +130,22 @@ impl SecretStorageKey {
         account_data_content_json: &str,
         secret_name: &SecretName,
     ) -> napi::Result<String> {
-        let mut content: SecretEventContent =
+        let content: SecretEventContent =
             serde_json::from_str(account_data_content_json).map_err(into_err)?;
+
+        self.decrypt_content(&content, secret_name)
+    }
+
+    /// Decrypt the given Secret Storage item, given as the deserialized
+    /// contents.
+    fn decrypt_content(
+        &self,
+        content: &SecretEventContent,
+        secret_name: &SecretName,
+    ) -> napi::Result<String> {
         let secret_data = content
             .encrypted
-            .remove(self.inner.key_id())
+            .get(self.inner.key_id())
             .ok_or(napi::Error::from_reason(format!("{secret_name} not encrypted with key")))?;
 
         let secret_data = secret_data.deserialize_as().map_err(into_err)?;
@@ -157,12 +176,190 @@ impl SecretStorageKey {
     }
 }
 
+/// A set of arbitrary named secrets, stored in Secret Storage.
+///
+/// Each secret is stored in its own account data event, whose type is the
+/// name of the secret, and can be encrypted with several secret storage
+/// keys.
+#[napi]
+#[derive(Default)]
+pub struct SecretStorage {
+    secrets: BTreeMap<String, SecretEventContent>,
+}
+
+#[napi]
+impl SecretStorage {
+    /// Create a new, empty, `SecretStorage`.
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an encrypted secret read from account data.
+    ///
+    /// If a secret with the same name exists already, the given encrypted
+    /// data are merged into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name`, the name of the secret, i.e. the type of the account
+    ///   data event.
+    /// * `content`, the JSON-encoded content of the account data event.
+    #[napi]
+    pub fn add_encrypted_secret(
+        &mut self,
+        secret_name: String,
+        content: String,
+    ) -> napi::Result<()> {
+        let content: SecretEventContent = serde_json::from_str(&content).map_err(into_err)?;
+
+        match self.secrets.get_mut(&secret_name) {
+            Some(secret) => secret.encrypted.extend(content.encrypted),
+            None => {
+                self.secrets.insert(secret_name, content);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set a secret, encrypted with each of the given keys.
+    ///
+    /// It replaces the secret with the same name, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name`, the name of the secret.
+    /// * `plaintext`, the secret.
+    /// * `keys`, the keys to encrypt the secret with.
+    #[napi(strict)]
+    pub fn set_secret(
+        &mut self,
+        secret_name: String,
+        plaintext: String,
+        keys: Vec<&SecretStorageKey>,
+    ) -> napi::Result<()> {
+        if keys.is_empty() {
+            return Err(napi::Error::from_reason(format!(
+                "{secret_name} must be encrypted with at least one key"
+            )));
+        }
+
+        let name = SecretName::from(secret_name.as_str());
+        let encrypted =
+            keys.iter().map(|key| (key.key_id(), key.encrypted_data(plaintext.clone(), &name)));
+
+        self.secrets.insert(secret_name, SecretEventContent::new(encrypted.collect()));
+
+        Ok(())
+    }
+
+    /// Decrypt a secret with the given key.
+    ///
+    /// Returns `null` if there is no secret with this name. Throws if the
+    /// secret isn't encrypted with the key.
+    #[napi(strict)]
+    pub fn get_secret(
+        &self,
+        secret_name: String,
+        key: &SecretStorageKey,
+    ) -> napi::Result<Option<String>> {
+        let Some(content) = self.secrets.get(&secret_name) else {
+            return Ok(None);
+        };
+
+        key.decrypt_content(content, &SecretName::from(secret_name.as_str())).map(Some)
+    }
+
+    /// Remove a secret.
+    ///
+    /// Returns `true` if the secret existed.
+    #[napi]
+    pub fn remove_secret(&mut self, secret_name: String) -> bool {
+        self.secrets.remove(&secret_name).is_some()
+    }
+
+    /// The names of all the secrets.
+    #[napi]
+    pub fn secret_names(&self) -> Vec<String> {
+        self.secrets.keys().cloned().collect()
+    }
+
+    /// The IDs of the keys a secret is encrypted with, or an empty array if
+    /// there is no secret with this name.
+    #[napi]
+    pub fn key_ids(&self, secret_name: String) -> Vec<String> {
+        self.secrets
+            .get(&secret_name)
+            .map(|content| content.encrypted.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Rotate a key: re-encrypt all the secrets encrypted with the old key
+    /// with the new key, and drop the data encrypted with the old key.
+    ///
+    /// The secrets that aren't encrypted with the old key are left
+    /// untouched. Nothing is changed if one of the secrets cannot be
+    /// decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `old_key`, the key to replace.
+    /// * `new_key`, the key to replace it with.
+    #[napi(strict)]
+    pub fn rotate_key(
+        &mut self,
+        old_key: &SecretStorageKey,
+        new_key: &SecretStorageKey,
+    ) -> napi::Result<()> {
+        let old_key_id = old_key.key_id();
+        let mut rotated = Vec::new();
+
+        for (secret_name, content) in &self.secrets {
+            if !content.encrypted.contains_key(&old_key_id) {
+                continue;
+            }
+
+            let name = SecretName::from(secret_name.as_str());
+            let plaintext = old_key.decrypt_content(content, &name)?;
+
+            rotated.push((secret_name.clone(), new_key.encrypted_data(plaintext, &name)));
+        }
+
+        for (secret_name, encrypted_data) in rotated {
+            let content = self.secrets.get_mut(&secret_name).expect("The secret exists");
+
+            content.encrypted.remove(&old_key_id);
+            content.encrypted.insert(new_key.key_id(), encrypted_data);
+        }
+
+        Ok(())
+    }
+
+    /// The account data events to upload, one per secret.
+    #[napi]
+    pub fn account_data(&self) -> napi::Result<Vec<AccountDataEvent>> {
+        self.secrets
+            .iter()
+            .map(|(secret_name, content)| {
+                Ok(AccountDataEvent {
+                    event_type: secret_name.clone(),
+                    content: serde_json::to_string(content).map_err(into_err)?,
+                })
+            })
+            .collect()
+    }
+}
+
 #[napi]
 /// The account data items containing the secrets, encoded as JSON
 pub struct SecretStorageItems {
     pub master_key: String,
     pub user_signing_key: String,
     pub self_signing_key: String,
+    /// The `m.megolm_backup.v1` item, holding the backup decryption key, if
+    /// any.
+    pub backup_key: Option<String>,
 }
 
 #[napi]
@@ -182,6 +379,85 @@ impl SecretStorageItems {
                 .get("selfSigningKey")
                 .ok_or(napi::Error::from_reason("missing self signing key"))?
                 .to_string(),
+            backup_key: items.get("backupKey").cloned(),
         })
     }
 }
+
+/// An account data event to upload to the server, with the
+/// `PUT /_matrix/client/v3/user/{userId}/account_data/{type}` endpoint.
+#[napi]
+#[derive(Clone)]
+pub struct AccountDataEvent {
+    /// The type of the event.
+    #[napi(readonly)]
+    pub event_type: String,
+
+    /// The JSON-encoded content of the event.
+    #[napi(readonly)]
+    pub content: String,
+}
+
+/// The result of `OlmMachine.bootstrapSecretStorage`.
+#[napi]
+pub struct SecretStorageBootstrapResult {
+    pub(crate) secret_storage_key: Option<SecretStorageKey>,
+    pub(crate) secret_storage_key_reference: Option<Reference<SecretStorageKey>>,
+    pub(crate) account_data: Vec<AccountDataEvent>,
+    pub(crate) cross_signing_bootstrap_requests: CrossSigningBootstrapRequests,
+    pub(crate) backup_decryption_key: store::types::BackupDecryptionKey,
+    pub(crate) backup_version_request: String,
+}
+
+#[napi]
+impl SecretStorageBootstrapResult {
+    /// The new secret storage key, which is the new default key.
+    ///
+    /// The same `SecretStorageKey` object is returned on every access.
+    #[napi(getter, ts_return_type = "SecretStorageKey")]
+    pub fn secret_storage_key(&mut self, env: Env) -> napi::Result<Reference<SecretStorageKey>> {
+        // The key can't be copied without deriving it again, so it's moved into
+        // its JS object the first time it's needed.
+        if let Some(secret_storage_key) = self.secret_storage_key.take() {
+            self.secret_storage_key_reference =
+                Some(SecretStorageKey::into_reference(secret_storage_key, env)?);
+        }
+
+        self.secret_storage_key_reference
+            .as_ref()
+            .ok_or_else(|| napi::Error::from_reason("missing secret storage key"))?
+            .clone(env)
+    }
+
+    /// The account data events to upload: the description of the secret
+    /// storage key, the `m.secret_storage.default_key` event, and the
+    /// encrypted secrets.
+    #[napi(getter)]
+    pub fn account_data(&self) -> Vec<AccountDataEvent> {
+        self.account_data.clone()
+    }
+
+    /// The requests to upload the new cross-signing keys.
+    #[napi(getter)]
+    pub fn cross_signing_bootstrap_requests(&self) -> CrossSigningBootstrapRequests {
+        self.cross_signing_bootstrap_requests.clone()
+    }
+
+    /// The decryption key of the new backup.
+    ///
+    /// It's already saved in the crypto store, without a backup version. Once
+    /// the backup version has been created, it should be saved again with
+    /// `OlmMachine.saveBackupDecryptionKey` to record the version, and the
+    /// backup enabled with `OlmMachine.enableBackupV1`.
+    #[napi(getter)]
+    pub fn backup_decryption_key(&self) -> BackupDecryptionKey {
+        BackupDecryptionKey { inner: self.backup_decryption_key.clone() }
+    }
+
+    /// The JSON-encoded body of the request to create the new backup version,
+    /// with the `POST /_matrix/client/v3/room_keys/version` endpoint.
+    #[napi(getter)]
+    pub fn backup_version_request(&self) -> String {
+        self.backup_version_request.clone()
+    }
+}
@@ -0,0 +1,1582 @@
+//! Crypto stores implemented in JavaScript, or kept in memory.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
+};
+
+use async_trait::async_trait;
+use matrix_sdk_common::{
+    cross_process_lock::{
+        memory_store_helper::{try_take_leased_lock, Lease},
+        CrossProcessLockGeneration,
+    },
+    ruma::{
+        events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
+    },
+};
+use matrix_sdk_crypto::{
+    olm::{
+        InboundGroupSession, OlmMessageHash, OutboundGroupSession, PickledAccount,
+        PickledCrossSigningIdentity, PickledInboundGroupSession, PickledOutboundGroupSession,
+        PickledSession, PrivateCrossSigningIdentity, SenderDataType, Session, StaticAccountData,
+    },
+    store::{
+        types::{
+            BackupDecryptionKey, BackupKeys, Changes, DehydratedDeviceKey, PendingChanges,
+            RoomKeyCounts, RoomKeyWithheldEntry, RoomSettings, StoredRoomKeyBundleData,
+            TrackedUser,
+        },
+        CryptoStore, CryptoStoreError,
+    },
+    vodozemac::{base64_decode, base64_encode, Curve25519PublicKey},
+    Account, DeviceData, GossipRequest, GossippedSecret, SecretInfo, UserIdentityData,
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use napi::{
+    bindgen_prelude::Promise,
+    threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction},
+    Env, JsFunction, JsObject, JsUnknown,
+};
+use napi_derive::*;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::into_err;
+
+/// The methods the JavaScript object given to `CustomStore` must
+/// implement.
+const METHODS: &[&str] = &[
+    "loadAccount",
+    "loadIdentity",
+    "saveChanges",
+    "getSessions",
+    "getInboundGroupSession",
+    "getInboundGroupSessions",
+    "getInboundGroupSessionCounts",
+    "getInboundGroupSessionsForDeviceBatch",
+    "getInboundGroupSessionsForBackup",
+    "markInboundGroupSessionsAsBackedUp",
+    "resetBackupState",
+    "getOutboundGroupSession",
+    "loadTrackedUsers",
+    "getDevice",
+    "getUserDevices",
+    "getUserIdentity",
+    "isMessageKnown",
+    "getGossipRequests",
+    "getGossipRequest",
+    "getGossipRequestByInfo",
+    "getSecretsFromInbox",
+    "getWithheldInfo",
+    "getRoomSettings",
+    "getReceivedRoomKeyBundleData",
+    "getCustomValue",
+];
+
+/// Custom values used to store the singletons of the store.
+const BACKUP_DECRYPTION_KEY: &str = "matrix-sdk-crypto-nodejs.backup_decryption_key";
+const BACKUP_VERSION: &str = "matrix-sdk-crypto-nodejs.backup_version";
+const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "matrix-sdk-crypto-nodejs.dehydrated_device_pickle_key";
+const NEXT_BATCH_TOKEN: &str = "matrix-sdk-crypto-nodejs.next_batch_token";
+
+/// A crypto store implemented in JavaScript, to be used with
+/// `StoreType.Custom`.
+///
+/// The given object must implement the following `async` methods. All
+/// the data are plain JSON values, that the object is expected to
+/// persist as-is.
+///
+/// ```text
+/// loadAccount(): Promise<object | null>
+/// loadIdentity(): Promise<object | null>
+/// saveChanges(changes: object): Promise<void>
+/// getSessions(senderKey: string): Promise<object[]>
+/// getInboundGroupSession(roomId: string, sessionId: string): Promise<object | null>
+/// getInboundGroupSessions(): Promise<object[]>
+/// getInboundGroupSessionCounts(
+///     backupVersion: string | null,
+/// ): Promise<{ total: number, backedUp: number }>
+/// getInboundGroupSessionsForDeviceBatch(
+///     senderKey: string,
+///     senderDataType: number,
+///     afterSessionId: string | null,
+///     limit: number,
+/// ): Promise<object[]>
+/// getInboundGroupSessionsForBackup(backupVersion: string, limit: number): Promise<object[]>
+/// markInboundGroupSessionsAsBackedUp(
+///     backupVersion: string,
+///     sessions: { roomId: string, sessionId: string }[],
+/// ): Promise<void>
+/// resetBackupState(): Promise<void>
+/// getOutboundGroupSession(roomId: string): Promise<object | null>
+/// loadTrackedUsers(): Promise<{ userId: string, dirty: boolean }[]>
+/// getDevice(userId: string, deviceId: string): Promise<object | null>
+/// getUserDevices(userId: string): Promise<object[]>
+/// getUserIdentity(userId: string): Promise<object | null>
+/// isMessageKnown(senderKey: string, hash: string): Promise<boolean>
+/// getGossipRequests(): Promise<object[]>
+/// getGossipRequest(requestId: string): Promise<object | null>
+/// getGossipRequestByInfo(infoKey: string): Promise<object | null>
+/// getSecretsFromInbox(secretName: string): Promise<object[]>
+/// getWithheldInfo(roomId: string, sessionId: string): Promise<object | null>
+/// getRoomSettings(roomId: string): Promise<object | null>
+/// getReceivedRoomKeyBundleData(roomId: string, userId: string): Promise<object | null>
+/// getCustomValue(key: string): Promise<string | null>
+/// ```
+///
+/// `saveChanges` receives an object that must be persisted
+/// atomically. Each of its fields is an array, of which each item
+/// must be saved, replacing any existing item with the same key:
+///
+/// * `account` and `privateIdentity`, zero or one pickle, returned by
+///   `loadAccount` and `loadIdentity` respectively,
+/// * `sessions`, `{ senderKey, sessionId, pickle }`, returned by `getSessions`
+///   as an array of `pickle`s,
+/// * `inboundGroupSessions`, `{ roomId, sessionId, senderKey, senderDataType,
+///   backedUp, backedUpToVersion, pickle }`, returned as-is by all the
+///   `getInboundGroupSession*` methods,
+/// * `outboundGroupSessions`, `{ roomId, pickle }`, returned by
+///   `getOutboundGroupSession` as `pickle`,
+/// * `messageHashes`, `{ senderKey, hash }`, used by `isMessageKnown`,
+/// * `devices`, `{ userId, deviceId, device }`, returned by `getDevice` and
+///   `getUserDevices` as `device`,
+/// * `userIdentities`, `{ userId, identity }`, returned by `getUserIdentity` as
+///   `identity`,
+/// * `gossipRequests`, `{ requestId, infoKey, request }`, returned by
+///   `getGossipRequest*` as `request`,
+/// * `secrets`, `{ secretName, secret }`, returned by `getSecretsFromInbox` as
+///   `secret`,
+/// * `withheldSessions`, `{ roomId, sessionId, info }`, returned by
+///   `getWithheldInfo` as `info`,
+/// * `roomSettings`, `{ roomId, settings }`, returned by `getRoomSettings` as
+///   `settings`,
+/// * `roomKeyBundles`, `{ roomId, userId, bundle }`, returned by
+///   `getReceivedRoomKeyBundleData` as `bundle`,
+/// * `trackedUsers`, `{ userId, dirty }`, returned by `loadTrackedUsers`,
+/// * `customValues`, `{ key, value }`, returned by `getCustomValue` as `value`,
+///   a base64-encoded string, be it a custom value of the `OlmMachine` or one
+///   of the singletons of the store; a `null` value means the custom value must
+///   be removed.
+///
+/// The following fields list the keys of the items that must be
+/// removed: `deletedDevices` (`{ userId, deviceId }`),
+/// `deletedGossipRequests` (`requestId`) and `deletedSecrets`
+/// (`secretName`, all the secrets with this name must be removed).
+///
+/// The other methods filter the stored items by their fields, so that
+/// only the matching ones cross the boundary between Rust and JavaScript:
+///
+/// * `getInboundGroupSessionCounts` counts all the inbound group sessions, and
+///   those whose `backedUpToVersion` is `backupVersion`, or, if `backupVersion`
+///   is `null`, those whose `backedUp` is `true`,
+/// * `getInboundGroupSessionsForDeviceBatch` returns at most `limit` inbound
+///   group sessions with the given `senderKey` and `senderDataType`, sorted by
+///   `sessionId`, and whose `sessionId` is greater than `afterSessionId` if it
+///   is not `null`,
+/// * `getInboundGroupSessionsForBackup` returns at most `limit` inbound group
+///   sessions whose `backedUpToVersion` is not `backupVersion`,
+/// * `markInboundGroupSessionsAsBackedUp` sets `backedUp` to `true` and
+///   `backedUpToVersion` to `backupVersion` on the given inbound group
+///   sessions, and `resetBackupState` sets them to `false` and `null` on all of
+///   them,
+/// * `getGossipRequest` and `getGossipRequestByInfo` return the gossip request
+///   with the given `requestId` or `infoKey`.
+#[napi]
+pub struct CustomStore {
+    pub(crate) inner: Arc<JsonCryptoStore>,
+}
+
+#[napi]
+impl CustomStore {
+    /// Create a new `CustomStore` backed by the given JavaScript
+    /// object.
+    #[napi(constructor)]
+    pub fn new(env: Env, implementation: JsObject) -> napi::Result<Self> {
+        let mut methods = HashMap::new();
+
+        for &name in METHODS {
+            let function: JsFunction = implementation.get_named_property(name).map_err(|_| {
+                napi::Error::from_reason(format!(
+                    "The custom store must implement the `{name}` method"
+                ))
+            })?;
+
+            // Bind the method to its object, so that `this` can be used in its body.
+            let bind: JsFunction = function.coerce_to_object()?.get_named_property("bind")?;
+            let function = JsFunction::try_from(
+                bind.call(Some(&function.coerce_to_object()?), &[&implementation])?,
+            )?;
+
+            let mut function: ThreadsafeFunction<Vec<Value>, ErrorStrategy::Fatal> = function
+                .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<Vec<Value>>| {
+                    ctx.value
+                        .iter()
+                        .map(|value| ctx.env.to_js_value(value))
+                        .collect::<napi::Result<Vec<JsUnknown>>>()
+                })?;
+
+            // Don't prevent Node.js from exiting because of the store.
+            function.unref(&env)?;
+
+            methods.insert(name, function);
+        }
+
+        Ok(Self { inner: Arc::new(JsonCryptoStore::new(Backend::Js(methods))) })
+    }
+}
+
+/// An error raised by the JavaScript implementation of a store.
+#[derive(Debug)]
+struct JsStoreError {
+    method: &'static str,
+    reason: String,
+}
+
+impl fmt::Display for JsStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The custom store failed to run `{}`: {}", self.method, self.reason)
+    }
+}
+
+impl std::error::Error for JsStoreError {}
+
+/// Where the data of a `JsonCryptoStore` are kept.
+enum Backend {
+    /// In a JavaScript object, see `CustomStore`.
+    Js(HashMap<&'static str, ThreadsafeFunction<Vec<Value>, ErrorStrategy::Fatal>>),
+
+    /// In memory, see `StoreType.Memory`.
+    Memory(StdMutex<MemoryBackend>),
+}
+
+/// A call to one of the methods of a `Backend`, i.e. to one of the methods
+/// of the JavaScript object given to `CustomStore`.
+enum StoreCall {
+    LoadAccount,
+    LoadIdentity,
+    SaveChanges(Value),
+    GetSessions {
+        sender_key: String,
+    },
+    GetInboundGroupSession {
+        room_id: String,
+        session_id: String,
+    },
+    GetInboundGroupSessions,
+    GetInboundGroupSessionCounts {
+        backup_version: Option<String>,
+    },
+    GetInboundGroupSessionsForDeviceBatch {
+        sender_key: String,
+        sender_data_type: u8,
+        after_session_id: Option<String>,
+        limit: usize,
+    },
+    GetInboundGroupSessionsForBackup {
+        backup_version: String,
+        limit: usize,
+    },
+    MarkInboundGroupSessionsAsBackedUp {
+        backup_version: String,
+        sessions: Vec<(String, String)>,
+    },
+    ResetBackupState,
+    GetOutboundGroupSession {
+        room_id: String,
+    },
+    LoadTrackedUsers,
+    GetDevice {
+        user_id: String,
+        device_id: String,
+    },
+    GetUserDevices {
+        user_id: String,
+    },
+    GetUserIdentity {
+        user_id: String,
+    },
+    IsMessageKnown {
+        sender_key: String,
+        hash: String,
+    },
+    GetGossipRequests,
+    GetGossipRequest {
+        request_id: String,
+    },
+    GetGossipRequestByInfo {
+        info_key: String,
+    },
+    GetSecretsFromInbox {
+        secret_name: String,
+    },
+    GetWithheldInfo {
+        room_id: String,
+        session_id: String,
+    },
+    GetRoomSettings {
+        room_id: String,
+    },
+    GetReceivedRoomKeyBundleData {
+        room_id: String,
+        user_id: String,
+    },
+    GetCustomValue {
+        key: String,
+    },
+}
+
+impl StoreCall {
+    /// The name of the JavaScript method implementing this call.
+    fn method(&self) -> &'static str {
+        match self {
+            Self::LoadAccount => "loadAccount",
+            Self::LoadIdentity => "loadIdentity",
+            Self::SaveChanges(..) => "saveChanges",
+            Self::GetSessions { .. } => "getSessions",
+            Self::GetInboundGroupSession { .. } => "getInboundGroupSession",
+            Self::GetInboundGroupSessions => "getInboundGroupSessions",
+            Self::GetInboundGroupSessionCounts { .. } => "getInboundGroupSessionCounts",
+            Self::GetInboundGroupSessionsForDeviceBatch { .. } => {
+                "getInboundGroupSessionsForDeviceBatch"
+            }
+            Self::GetInboundGroupSessionsForBackup { .. } => "getInboundGroupSessionsForBackup",
+            Self::MarkInboundGroupSessionsAsBackedUp { .. } => "markInboundGroupSessionsAsBackedUp",
+            Self::ResetBackupState => "resetBackupState",
+            Self::GetOutboundGroupSession { .. } => "getOutboundGroupSession",
+            Self::LoadTrackedUsers => "loadTrackedUsers",
+            Self::GetDevice { .. } => "getDevice",
+            Self::GetUserDevices { .. } => "getUserDevices",
+            Self::GetUserIdentity { .. } => "getUserIdentity",
+            Self::IsMessageKnown { .. } => "isMessageKnown",
+            Self::GetGossipRequests => "getGossipRequests",
+            Self::GetGossipRequest { .. } => "getGossipRequest",
+            Self::GetGossipRequestByInfo { .. } => "getGossipRequestByInfo",
+            Self::GetSecretsFromInbox { .. } => "getSecretsFromInbox",
+            Self::GetWithheldInfo { .. } => "getWithheldInfo",
+            Self::GetRoomSettings { .. } => "getRoomSettings",
+            Self::GetReceivedRoomKeyBundleData { .. } => "getReceivedRoomKeyBundleData",
+            Self::GetCustomValue { .. } => "getCustomValue",
+        }
+    }
+
+    /// The arguments of the JavaScript method implementing this call.
+    fn into_args(self) -> Vec<Value> {
+        match self {
+            Self::LoadAccount
+            | Self::LoadIdentity
+            | Self::GetInboundGroupSessions
+            | Self::ResetBackupState
+            | Self::LoadTrackedUsers
+            | Self::GetGossipRequests => vec![],
+            Self::SaveChanges(changes) => vec![changes],
+            Self::GetSessions { sender_key } => vec![json!(sender_key)],
+            Self::GetInboundGroupSession { room_id, session_id }
+            | Self::GetWithheldInfo { room_id, session_id } => {
+                vec![json!(room_id), json!(session_id)]
+            }
+            Self::GetInboundGroupSessionCounts { backup_version } => vec![json!(backup_version)],
+            Self::GetInboundGroupSessionsForDeviceBatch {
+                sender_key,
+                sender_data_type,
+                after_session_id,
+                limit,
+            } => vec![
+                json!(sender_key),
+                json!(sender_data_type),
+                json!(after_session_id),
+                json!(limit),
+            ],
+            Self::GetInboundGroupSessionsForBackup { backup_version, limit } => {
+                vec![json!(backup_version), json!(limit)]
+            }
+            Self::MarkInboundGroupSessionsAsBackedUp { backup_version, sessions } => {
+                let sessions = sessions
+                    .into_iter()
+                    .map(|(room_id, session_id)| {
+                        json!({ "roomId": room_id, "sessionId": session_id })
+                    })
+                    .collect::<Vec<_>>();
+
+                vec![json!(backup_version), json!(sessions)]
+            }
+            Self::GetOutboundGroupSession { room_id } | Self::GetRoomSettings { room_id } => {
+                vec![json!(room_id)]
+            }
+            Self::GetDevice { user_id, device_id } => vec![json!(user_id), json!(device_id)],
+            Self::GetUserDevices { user_id } | Self::GetUserIdentity { user_id } => {
+                vec![json!(user_id)]
+            }
+            Self::IsMessageKnown { sender_key, hash } => vec![json!(sender_key), json!(hash)],
+            Self::GetGossipRequest { request_id } => vec![json!(request_id)],
+            Self::GetGossipRequestByInfo { info_key } => vec![json!(info_key)],
+            Self::GetSecretsFromInbox { secret_name } => vec![json!(secret_name)],
+            Self::GetReceivedRoomKeyBundleData { room_id, user_id } => {
+                vec![json!(room_id), json!(user_id)]
+            }
+            Self::GetCustomValue { key } => vec![json!(key)],
+        }
+    }
+}
+
+/// A `CryptoStore` implementation whose data are serialized to JSON
+/// values, and kept by a `Backend`.
+pub(crate) struct JsonCryptoStore {
+    backend: Backend,
+    static_account: StdRwLock<Option<StaticAccountData>>,
+    leases: StdRwLock<HashMap<String, Lease>>,
+}
+
+impl fmt::Debug for JsonCryptoStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonCryptoStore").finish_non_exhaustive()
+    }
+}
+
+/// Serialize a value that is going to be sent to JavaScript.
+fn to_value<T: Serialize>(value: &T) -> Result<Value, CryptoStoreError> {
+    Ok(serde_json::to_value(value)?)
+}
+
+/// Deserialize a value that is coming from JavaScript.
+fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, CryptoStoreError> {
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Get a field of an object coming from JavaScript.
+fn field(value: &mut Value, name: &str) -> Value {
+    value.get_mut(name).map(Value::take).unwrap_or_default()
+}
+
+/// A `customValues` item, holding the base64-encoded `value`, or `null` to
+/// remove it.
+fn custom_value(key: &str, value: Option<&[u8]>) -> Value {
+    json!({ "key": key, "value": value.map(base64_encode) })
+}
+
+/// An empty set of changes to send to `saveChanges`.
+fn empty_changes() -> Value {
+    json!({
+        "account": [],
+        "privateIdentity": [],
+        "sessions": [],
+        "inboundGroupSessions": [],
+        "outboundGroupSessions": [],
+        "messageHashes": [],
+        "devices": [],
+        "deletedDevices": [],
+        "userIdentities": [],
+        "gossipRequests": [],
+        "deletedGossipRequests": [],
+        "secrets": [],
+        "deletedSecrets": [],
+        "withheldSessions": [],
+        "roomSettings": [],
+        "roomKeyBundles": [],
+        "trackedUsers": [],
+        "customValues": [],
+    })
+}
+
+/// Push an item to one of the arrays of the changes.
+fn push(changes: &mut Value, name: &str, item: Value) {
+    if let Some(Value::Array(items)) = changes.get_mut(name) {
+        items.push(item);
+    }
+}
+
+/// The tables of the `MemoryBackend`.
+#[derive(Clone, Copy)]
+enum Table {
+    Account,
+    PrivateIdentity,
+    Sessions,
+    InboundGroupSessions,
+    OutboundGroupSessions,
+    MessageHashes,
+    Devices,
+    UserIdentities,
+    GossipRequests,
+    Secrets,
+    WithheldSessions,
+    RoomSettings,
+    RoomKeyBundles,
+    TrackedUsers,
+    CustomValues,
+}
+
+impl Table {
+    const ALL: [Self; 15] = [
+        Self::Account,
+        Self::PrivateIdentity,
+        Self::Sessions,
+        Self::InboundGroupSessions,
+        Self::OutboundGroupSessions,
+        Self::MessageHashes,
+        Self::Devices,
+        Self::UserIdentities,
+        Self::GossipRequests,
+        Self::Secrets,
+        Self::WithheldSessions,
+        Self::RoomSettings,
+        Self::RoomKeyBundles,
+        Self::TrackedUsers,
+        Self::CustomValues,
+    ];
+
+    /// The name of the table, which is also the name of the field of the
+    /// changes given to `saveChanges` that lists its new items.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::PrivateIdentity => "privateIdentity",
+            Self::Sessions => "sessions",
+            Self::InboundGroupSessions => "inboundGroupSessions",
+            Self::OutboundGroupSessions => "outboundGroupSessions",
+            Self::MessageHashes => "messageHashes",
+            Self::Devices => "devices",
+            Self::UserIdentities => "userIdentities",
+            Self::GossipRequests => "gossipRequests",
+            Self::Secrets => "secrets",
+            Self::WithheldSessions => "withheldSessions",
+            Self::RoomSettings => "roomSettings",
+            Self::RoomKeyBundles => "roomKeyBundles",
+            Self::TrackedUsers => "trackedUsers",
+            Self::CustomValues => "customValues",
+        }
+    }
+
+    /// The fields of the items that are used as their key.
+    fn key_fields(self) -> &'static [&'static str] {
+        match self {
+            Self::Account | Self::PrivateIdentity => &[],
+            Self::Sessions => &["senderKey", "sessionId"],
+            Self::InboundGroupSessions | Self::WithheldSessions => &["roomId", "sessionId"],
+            Self::OutboundGroupSessions | Self::RoomSettings => &["roomId"],
+            Self::MessageHashes => &["senderKey", "hash"],
+            Self::Devices => &["userId", "deviceId"],
+            Self::UserIdentities | Self::TrackedUsers => &["userId"],
+            Self::GossipRequests => &["requestId"],
+            Self::Secrets => &["secretName", "secret"],
+            Self::RoomKeyBundles => &["roomId", "userId"],
+            Self::CustomValues => &["key"],
+        }
+    }
+
+    /// The field of the items that is returned when they are looked up, if
+    /// not the whole item.
+    fn value_field(self) -> Option<&'static str> {
+        match self {
+            Self::Account
+            | Self::PrivateIdentity
+            | Self::InboundGroupSessions
+            | Self::MessageHashes
+            | Self::TrackedUsers => None,
+            Self::Sessions | Self::OutboundGroupSessions => Some("pickle"),
+            Self::Devices => Some("device"),
+            Self::UserIdentities => Some("identity"),
+            Self::GossipRequests => Some("request"),
+            Self::Secrets => Some("secret"),
+            Self::WithheldSessions => Some("info"),
+            Self::RoomSettings => Some("settings"),
+            Self::RoomKeyBundles => Some("bundle"),
+            Self::CustomValues => Some("value"),
+        }
+    }
+
+    /// Get the value of an item that is returned when it is looked up.
+    fn value(self, item: &Value) -> Value {
+        match self.value_field() {
+            Some(value_field) => item[value_field].clone(),
+            None => item.clone(),
+        }
+    }
+}
+
+/// A `Backend` keeping all the data in memory, implementing the same
+/// methods as the JavaScript objects given to `CustomStore`.
+#[derive(Default)]
+struct MemoryBackend {
+    tables: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl MemoryBackend {
+    /// Compute the key of an item, from the values of its key fields.
+    fn key<'a>(values: impl IntoIterator<Item = &'a Value>) -> String {
+        Value::Array(values.into_iter().cloned().collect()).to_string()
+    }
+
+    fn table(&self, table: Table) -> impl Iterator<Item = &Value> {
+        self.tables.get(table.name()).into_iter().flat_map(BTreeMap::values)
+    }
+
+    fn table_mut(&mut self, table: Table) -> &mut BTreeMap<String, Value> {
+        self.tables.entry(table.name().to_owned()).or_default()
+    }
+
+    /// Find the item with the given key, and return its value.
+    fn get<const N: usize>(&self, table: Table, key: [&str; N]) -> Value {
+        let key = Self::key(&key.map(Value::from));
+
+        self.tables
+            .get(table.name())
+            .and_then(|items| items.get(&key))
+            .map(|item| table.value(item))
+            .unwrap_or_default()
+    }
+
+    /// Find all the items whose `field` has the given value, and return their
+    /// values.
+    fn filter(&self, table: Table, field: &str, value: &str) -> Value {
+        self.table(table)
+            .filter(|item| item[field] == value)
+            .map(|item| table.value(item))
+            .collect()
+    }
+
+    /// Find all the inbound group sessions matching `predicate`.
+    fn inbound_group_sessions(&self, predicate: impl Fn(&Value) -> bool) -> Vec<&Value> {
+        self.table(Table::InboundGroupSessions).filter(|item| predicate(item)).collect()
+    }
+
+    fn save_changes(&mut self, mut changes: Value) {
+        for table in Table::ALL {
+            let Value::Array(items) = field(&mut changes, table.name()) else {
+                continue;
+            };
+
+            let key_fields = table.key_fields();
+            let items_by_key = self.table_mut(table);
+
+            for item in items {
+                let key = Self::key(key_fields.iter().map(|key_field| &item[key_field]));
+
+                // A `null` custom value means the custom value must be removed.
+                if matches!(table, Table::CustomValues) && item["value"].is_null() {
+                    items_by_key.remove(&key);
+                } else {
+                    items_by_key.insert(key, item);
+                }
+            }
+        }
+
+        if let Value::Array(deleted) = field(&mut changes, "deletedDevices") {
+            let devices = self.table_mut(Table::Devices);
+
+            for deleted in deleted {
+                devices.remove(&Self::key([&deleted["userId"], &deleted["deviceId"]]));
+            }
+        }
+
+        if let Value::Array(deleted) = field(&mut changes, "deletedGossipRequests") {
+            let gossip_requests = self.table_mut(Table::GossipRequests);
+
+            for deleted in deleted {
+                gossip_requests.remove(&Self::key([&deleted]));
+            }
+        }
+
+        if let Value::Array(deleted) = field(&mut changes, "deletedSecrets") {
+            // All the secrets with this name are removed.
+            self.table_mut(Table::Secrets).retain(|_, item| !deleted.contains(&item["secretName"]));
+        }
+    }
+
+    fn call(&mut self, call: StoreCall) -> Value {
+        match call {
+            StoreCall::LoadAccount => self.get(Table::Account, []),
+            StoreCall::LoadIdentity => self.get(Table::PrivateIdentity, []),
+            StoreCall::SaveChanges(changes) => {
+                self.save_changes(changes);
+
+                Value::Null
+            }
+            StoreCall::GetSessions { sender_key } => {
+                self.filter(Table::Sessions, "senderKey", &sender_key)
+            }
+            StoreCall::GetInboundGroupSession { room_id, session_id } => {
+                self.get(Table::InboundGroupSessions, [&room_id, &session_id])
+            }
+            StoreCall::GetInboundGroupSessions => {
+                self.table(Table::InboundGroupSessions).cloned().collect()
+            }
+            StoreCall::GetInboundGroupSessionCounts { backup_version } => {
+                let total = self.table(Table::InboundGroupSessions).count();
+                let backed_up = self
+                    .inbound_group_sessions(|item| match &backup_version {
+                        Some(backup_version) => item["backedUpToVersion"] == *backup_version,
+                        None => item["backedUp"] == true,
+                    })
+                    .len();
+
+                json!({ "total": total, "backedUp": backed_up })
+            }
+            StoreCall::GetInboundGroupSessionsForDeviceBatch {
+                sender_key,
+                sender_data_type,
+                after_session_id,
+                limit,
+            } => {
+                let mut sessions = self.inbound_group_sessions(|item| {
+                    item["senderKey"] == sender_key
+                        && item["senderDataType"] == sender_data_type
+                        && after_session_id.as_deref().map_or(true, |after_session_id| {
+                            item["sessionId"].as_str() > Some(after_session_id)
+                        })
+                });
+
+                sessions.sort_by(|a, b| a["sessionId"].as_str().cmp(&b["sessionId"].as_str()));
+
+                sessions.into_iter().take(limit).cloned().collect()
+            }
+            StoreCall::GetInboundGroupSessionsForBackup { backup_version, limit } => self
+                .inbound_group_sessions(|item| item["backedUpToVersion"] != backup_version)
+                .into_iter()
+                .take(limit)
+                .cloned()
+                .collect(),
+            StoreCall::MarkInboundGroupSessionsAsBackedUp { backup_version, sessions } => {
+                let items = self.table_mut(Table::InboundGroupSessions);
+
+                for (room_id, session_id) in sessions {
+                    let key = Self::key(&[Value::from(room_id), Value::from(session_id)]);
+
+                    if let Some(item) = items.get_mut(&key) {
+                        item["backedUp"] = Value::Bool(true);
+                        item["backedUpToVersion"] = Value::from(backup_version.as_str());
+                    }
+                }
+
+                Value::Null
+            }
+            StoreCall::ResetBackupState => {
+                for item in self.table_mut(Table::InboundGroupSessions).values_mut() {
+                    item["backedUp"] = Value::Bool(false);
+                    item["backedUpToVersion"] = Value::Null;
+                }
+
+                Value::Null
+            }
+            StoreCall::GetOutboundGroupSession { room_id } => {
+                self.get(Table::OutboundGroupSessions, [&room_id])
+            }
+            StoreCall::LoadTrackedUsers => self.table(Table::TrackedUsers).cloned().collect(),
+            StoreCall::GetDevice { user_id, device_id } => {
+                self.get(Table::Devices, [&user_id, &device_id])
+            }
+            StoreCall::GetUserDevices { user_id } => {
+                self.filter(Table::Devices, "userId", &user_id)
+            }
+            StoreCall::GetUserIdentity { user_id } => self.get(Table::UserIdentities, [&user_id]),
+            StoreCall::IsMessageKnown { sender_key, hash } => {
+                Value::Bool(!self.get(Table::MessageHashes, [&sender_key, &hash]).is_null())
+            }
+            StoreCall::GetGossipRequests => self
+                .table(Table::GossipRequests)
+                .map(|item| Table::GossipRequests.value(item))
+                .collect(),
+            StoreCall::GetGossipRequest { request_id } => {
+                self.get(Table::GossipRequests, [&request_id])
+            }
+            StoreCall::GetGossipRequestByInfo { info_key } => self
+                .table(Table::GossipRequests)
+                .find(|item| item["infoKey"] == info_key)
+                .map(|item| Table::GossipRequests.value(item))
+                .unwrap_or_default(),
+            StoreCall::GetSecretsFromInbox { secret_name } => {
+                self.filter(Table::Secrets, "secretName", &secret_name)
+            }
+            StoreCall::GetWithheldInfo { room_id, session_id } => {
+                self.get(Table::WithheldSessions, [&room_id, &session_id])
+            }
+            StoreCall::GetRoomSettings { room_id } => self.get(Table::RoomSettings, [&room_id]),
+            StoreCall::GetReceivedRoomKeyBundleData { room_id, user_id } => {
+                self.get(Table::RoomKeyBundles, [&room_id, &user_id])
+            }
+            StoreCall::GetCustomValue { key } => self.get(Table::CustomValues, [&key]),
+        }
+    }
+}
+
+impl JsonCryptoStore {
+    fn new(backend: Backend) -> Self {
+        Self { backend, static_account: Default::default(), leases: Default::default() }
+    }
+
+    /// Create a new store keeping its data in memory.
+    pub(crate) fn new_in_memory() -> Self {
+        Self::new(Backend::Memory(Default::default()))
+    }
+
+    /// Create a new store keeping its data in memory, restored from a
+    /// snapshot created by `snapshot`.
+    pub(crate) fn from_snapshot(snapshot: &[u8], passphrase: &str) -> napi::Result<Self> {
+        let snapshot: Value = serde_json::from_slice(snapshot).map_err(into_err)?;
+        let cipher = snapshot
+            .get("cipher")
+            .and_then(Value::as_str)
+            .ok_or_else(|| napi::Error::from_reason("The snapshot is malformed"))?;
+        let tables = snapshot
+            .get("tables")
+            .and_then(Value::as_str)
+            .ok_or_else(|| napi::Error::from_reason("The snapshot is malformed"))?;
+
+        let cipher = StoreCipher::import(passphrase, &base64_decode(cipher).map_err(into_err)?)
+            .map_err(into_err)?;
+        let tables =
+            cipher.decrypt_value(&base64_decode(tables).map_err(into_err)?).map_err(into_err)?;
+
+        Ok(Self::new(Backend::Memory(StdMutex::new(MemoryBackend { tables }))))
+    }
+
+    /// Serialize all the data of an in-memory store to a blob,
+    /// encrypted with the given passphrase.
+    ///
+    /// Returns `None` if the store doesn't keep its data in memory.
+    pub(crate) fn snapshot(&self, passphrase: &str) -> napi::Result<Option<Vec<u8>>> {
+        let Backend::Memory(backend) = &self.backend else {
+            return Ok(None);
+        };
+
+        let cipher = StoreCipher::new().map_err(into_err)?;
+        let tables = cipher.encrypt_value(&backend.lock().unwrap().tables).map_err(into_err)?;
+
+        let snapshot = json!({
+            "cipher": base64_encode(cipher.export(passphrase).map_err(into_err)?),
+            "tables": base64_encode(tables),
+        });
+
+        Ok(Some(serde_json::to_vec(&snapshot).map_err(into_err)?))
+    }
+
+    /// Call a method of the backend, i.e. for a JavaScript object, call the
+    /// method and wait for the promise it returns to resolve.
+    async fn call(&self, call: StoreCall) -> Result<Value, CryptoStoreError> {
+        let methods = match &self.backend {
+            Backend::Js(methods) => methods,
+            Backend::Memory(backend) => return Ok(backend.lock().unwrap().call(call)),
+        };
+
+        let method = call.method();
+        let to_error = |error: napi::Error| {
+            CryptoStoreError::backend(JsStoreError { method, reason: error.reason })
+        };
+
+        let function = methods
+            .get(method)
+            .ok_or_else(|| to_error(napi::Error::from_reason("the method is not implemented")))?;
+        let promise: Promise<Value> =
+            function.call_async(call.into_args()).await.map_err(to_error)?;
+
+        promise.await.map_err(to_error)
+    }
+
+    /// Call a method that returns an optional value.
+    async fn call_optional<T: DeserializeOwned>(
+        &self,
+        call: StoreCall,
+    ) -> Result<Option<T>, CryptoStoreError> {
+        match self.call(call).await? {
+            Value::Null => Ok(None),
+            value => Ok(Some(from_value(value)?)),
+        }
+    }
+
+    /// Call a method that returns an array of values.
+    async fn call_array(&self, call: StoreCall) -> Result<Vec<Value>, CryptoStoreError> {
+        match self.call(call).await? {
+            Value::Null => Ok(Vec::new()),
+            value => from_value(value),
+        }
+    }
+
+    async fn save(&self, changes: Value) -> Result<(), CryptoStoreError> {
+        self.call(StoreCall::SaveChanges(changes)).await?;
+
+        Ok(())
+    }
+
+    fn static_account(&self) -> Result<StaticAccountData, CryptoStoreError> {
+        self.static_account.read().unwrap().clone().ok_or(CryptoStoreError::AccountUnset)
+    }
+
+    async fn inbound_group_session_record(
+        &self,
+        session: &InboundGroupSession,
+        backed_up_to_version: Option<&str>,
+    ) -> Result<Value, CryptoStoreError> {
+        // Saving a backed up session again must not forget the version of the
+        // backup it's been uploaded to, otherwise it'd be uploaded again.
+        let backed_up_to_version = match backed_up_to_version {
+            Some(backed_up_to_version) => Some(backed_up_to_version.to_owned()),
+            None if session.backed_up() => self
+                .call_optional::<Value>(StoreCall::GetInboundGroupSession {
+                    room_id: session.room_id().to_string(),
+                    session_id: session.session_id().to_owned(),
+                })
+                .await?
+                .and_then(|mut record| {
+                    from_value::<Option<String>>(field(&mut record, "backedUpToVersion"))
+                        .ok()
+                        .flatten()
+                }),
+            None => None,
+        };
+
+        Ok(json!({
+            "roomId": session.room_id(),
+            "sessionId": session.session_id(),
+            "senderKey": session.sender_key().to_base64(),
+            "senderDataType": session.sender_data.to_type() as u8,
+            "backedUp": session.backed_up() || backed_up_to_version.is_some(),
+            "backedUpToVersion": backed_up_to_version,
+            "pickle": to_value(&session.pickle().await)?,
+        }))
+    }
+
+    /// Turn a record saved by `inbound_group_session_record` back into a
+    /// session.
+    fn inbound_group_session_from_record(
+        mut record: Value,
+    ) -> Result<InboundGroupSession, CryptoStoreError> {
+        let backed_up: bool = from_value(field(&mut record, "backedUp"))?;
+        let pickle: PickledInboundGroupSession = from_value(field(&mut record, "pickle"))?;
+        let session = InboundGroupSession::from_pickle(pickle)
+            .map_err(|_| CryptoStoreError::UnpicklingError)?;
+
+        // The backup state is updated by the store without touching the
+        // pickle, so the fields of the record take precedence.
+        if backed_up {
+            session.mark_as_backed_up();
+        } else {
+            session.reset_backup_state();
+        }
+
+        Ok(session)
+    }
+
+    /// Call a method that returns an array of inbound group session records.
+    async fn call_inbound_group_sessions(
+        &self,
+        call: StoreCall,
+    ) -> Result<Vec<InboundGroupSession>, CryptoStoreError> {
+        self.call_array(call)
+            .await?
+            .into_iter()
+            .map(Self::inbound_group_session_from_record)
+            .collect()
+    }
+
+    async fn all_gossip_requests(&self) -> Result<Vec<GossipRequest>, CryptoStoreError> {
+        self.call_array(StoreCall::GetGossipRequests).await?.into_iter().map(from_value).collect()
+    }
+
+    /// Get a custom value holding a UTF-8 string, like the singletons of the
+    /// store.
+    async fn get_custom_string(&self, key: &str) -> Result<Option<String>, CryptoStoreError> {
+        self.get_custom_value(key)
+            .await?
+            .map(String::from_utf8)
+            .transpose()
+            .map_err(|_| CryptoStoreError::UnpicklingError)
+    }
+}
+
+#[async_trait]
+impl CryptoStore for JsonCryptoStore {
+    type Error = CryptoStoreError;
+
+    async fn load_account(&self) -> Result<Option<Account>, Self::Error> {
+        let Some(pickle) = self.call_optional::<PickledAccount>(StoreCall::LoadAccount).await?
+        else {
+            return Ok(None);
+        };
+
+        let account =
+            Account::from_pickle(pickle).map_err(|_| CryptoStoreError::UnpicklingError)?;
+        *self.static_account.write().unwrap() = Some(account.static_data().clone());
+
+        Ok(Some(account))
+    }
+
+    async fn load_identity(&self) -> Result<Option<PrivateCrossSigningIdentity>, Self::Error> {
+        let Some(pickle) =
+            self.call_optional::<PickledCrossSigningIdentity>(StoreCall::LoadIdentity).await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            PrivateCrossSigningIdentity::from_pickle(pickle)
+                .map_err(|_| CryptoStoreError::UnpicklingError)?,
+        ))
+    }
+
+    async fn save_pending_changes(&self, changes: PendingChanges) -> Result<(), Self::Error> {
+        let Some(account) = changes.account else {
+            return Ok(());
+        };
+
+        let mut js_changes = empty_changes();
+        push(&mut js_changes, "account", to_value(&account.pickle())?);
+        self.save(js_changes).await?;
+
+        *self.static_account.write().unwrap() = Some(account.static_data().clone());
+
+        Ok(())
+    }
+
+    async fn save_changes(&self, changes: Changes) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+
+        if let Some(identity) = &changes.private_identity {
+            push(&mut js_changes, "privateIdentity", to_value(&identity.pickle().await)?);
+        }
+
+        if let Some(decryption_key) = &changes.backup_decryption_key {
+            push(
+                &mut js_changes,
+                "customValues",
+                custom_value(BACKUP_DECRYPTION_KEY, Some(decryption_key.to_base64().as_bytes())),
+            );
+        }
+
+        if let Some(backup_version) = &changes.backup_version {
+            push(
+                &mut js_changes,
+                "customValues",
+                custom_value(BACKUP_VERSION, Some(backup_version.as_bytes())),
+            );
+        }
+
+        if let Some(pickle_key) = &changes.dehydrated_device_pickle_key {
+            push(
+                &mut js_changes,
+                "customValues",
+                custom_value(DEHYDRATED_DEVICE_PICKLE_KEY, Some(pickle_key.to_base64().as_bytes())),
+            );
+        }
+
+        if let Some(next_batch_token) = &changes.next_batch_token {
+            push(
+                &mut js_changes,
+                "customValues",
+                custom_value(NEXT_BATCH_TOKEN, Some(next_batch_token.as_bytes())),
+            );
+        }
+
+        for session in &changes.sessions {
+            push(
+                &mut js_changes,
+                "sessions",
+                json!({
+                    "senderKey": session.sender_key.to_base64(),
+                    "sessionId": session.session_id(),
+                    "pickle": to_value(&session.pickle().await)?,
+                }),
+            );
+        }
+
+        for hash in &changes.message_hashes {
+            push(
+                &mut js_changes,
+                "messageHashes",
+                json!({ "senderKey": hash.sender_key, "hash": hash.hash }),
+            );
+        }
+
+        for session in &changes.inbound_group_sessions {
+            push(
+                &mut js_changes,
+                "inboundGroupSessions",
+                self.inbound_group_session_record(session, None).await?,
+            );
+        }
+
+        for session in &changes.outbound_group_sessions {
+            push(
+                &mut js_changes,
+                "outboundGroupSessions",
+                json!({
+                    "roomId": session.room_id(),
+                    "pickle": to_value(&session.pickle().await)?,
+                }),
+            );
+        }
+
+        for request in &changes.key_requests {
+            push(
+                &mut js_changes,
+                "gossipRequests",
+                json!({
+                    "requestId": request.request_id,
+                    "infoKey": request.info.as_key(),
+                    "request": to_value(request)?,
+                }),
+            );
+        }
+
+        let identities = &changes.identities;
+
+        for identity in identities.new.iter().chain(&identities.changed) {
+            push(
+                &mut js_changes,
+                "userIdentities",
+                json!({ "userId": identity.user_id(), "identity": to_value(identity)? }),
+            );
+        }
+
+        let devices = &changes.devices;
+
+        for device in devices.new.iter().chain(&devices.changed) {
+            push(
+                &mut js_changes,
+                "devices",
+                json!({
+                    "userId": device.user_id(),
+                    "deviceId": device.device_id(),
+                    "device": to_value(device)?,
+                }),
+            );
+        }
+
+        for device in &devices.deleted {
+            push(
+                &mut js_changes,
+                "deletedDevices",
+                json!({ "userId": device.user_id(), "deviceId": device.device_id() }),
+            );
+        }
+
+        for (room_id, sessions) in &changes.withheld_session_info {
+            for (session_id, info) in sessions {
+                push(
+                    &mut js_changes,
+                    "withheldSessions",
+                    json!({ "roomId": room_id, "sessionId": session_id, "info": to_value(info)? }),
+                );
+            }
+        }
+
+        for (room_id, settings) in &changes.room_settings {
+            push(
+                &mut js_changes,
+                "roomSettings",
+                json!({ "roomId": room_id, "settings": to_value(settings)? }),
+            );
+        }
+
+        for secret in &changes.secrets {
+            push(
+                &mut js_changes,
+                "secrets",
+                json!({ "secretName": secret.secret_name.as_str(), "secret": to_value(secret)? }),
+            );
+        }
+
+        for bundle in &changes.received_room_key_bundles {
+            push(
+                &mut js_changes,
+                "roomKeyBundles",
+                json!({
+                    "roomId": bundle.bundle_data.room_id,
+                    "userId": bundle.sender_user,
+                    "bundle": to_value(bundle)?,
+                }),
+            );
+        }
+
+        self.save(js_changes).await
+    }
+
+    async fn save_inbound_group_sessions(
+        &self,
+        sessions: Vec<InboundGroupSession>,
+        backed_up_to_version: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+
+        for session in &sessions {
+            push(
+                &mut js_changes,
+                "inboundGroupSessions",
+                self.inbound_group_session_record(session, backed_up_to_version).await?,
+            );
+        }
+
+        self.save(js_changes).await
+    }
+
+    async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>, Self::Error> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        let sessions = self
+            .call_array(StoreCall::GetSessions { sender_key: sender_key.to_owned() })
+            .await?
+            .into_iter()
+            .map(|pickle| {
+                let pickle: PickledSession = from_value(pickle)?;
+
+                Session::from_pickle(device_keys.clone(), pickle)
+                    .map_err(|_| CryptoStoreError::UnpicklingError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((!sessions.is_empty()).then_some(sessions))
+    }
+
+    async fn get_inbound_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<InboundGroupSession>, Self::Error> {
+        let Some(record) = self
+            .call_optional::<Value>(StoreCall::GetInboundGroupSession {
+                room_id: room_id.to_string(),
+                session_id: session_id.to_owned(),
+            })
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self::inbound_group_session_from_record(record)?))
+    }
+
+    async fn get_withheld_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<RoomKeyWithheldEntry>, Self::Error> {
+        self.call_optional(StoreCall::GetWithheldInfo {
+            room_id: room_id.to_string(),
+            session_id: session_id.to_owned(),
+        })
+        .await
+    }
+
+    async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>, Self::Error> {
+        self.call_inbound_group_sessions(StoreCall::GetInboundGroupSessions).await
+    }
+
+    async fn inbound_group_session_counts(
+        &self,
+        backup_version: Option<&str>,
+    ) -> Result<RoomKeyCounts, Self::Error> {
+        let mut counts = self
+            .call(StoreCall::GetInboundGroupSessionCounts {
+                backup_version: backup_version.map(ToOwned::to_owned),
+            })
+            .await?;
+
+        Ok(RoomKeyCounts {
+            total: from_value(field(&mut counts, "total"))?,
+            backed_up: from_value(field(&mut counts, "backedUp"))?,
+        })
+    }
+
+    async fn get_inbound_group_sessions_for_device_batch(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+        after_session_id: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error> {
+        self.call_inbound_group_sessions(StoreCall::GetInboundGroupSessionsForDeviceBatch {
+            sender_key: sender_key.to_base64(),
+            sender_data_type: sender_data_type as u8,
+            after_session_id,
+            limit,
+        })
+        .await
+    }
+
+    async fn inbound_group_sessions_for_backup(
+        &self,
+        backup_version: &str,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error> {
+        self.call_inbound_group_sessions(StoreCall::GetInboundGroupSessionsForBackup {
+            backup_version: backup_version.to_owned(),
+            limit,
+        })
+        .await
+    }
+
+    async fn mark_inbound_group_sessions_as_backed_up(
+        &self,
+        backup_version: &str,
+        room_and_session_ids: &[(&RoomId, &str)],
+    ) -> Result<(), Self::Error> {
+        let sessions = room_and_session_ids
+            .iter()
+            .map(|(room_id, session_id)| (room_id.to_string(), session_id.to_string()))
+            .collect();
+
+        self.call(StoreCall::MarkInboundGroupSessionsAsBackedUp {
+            backup_version: backup_version.to_owned(),
+            sessions,
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reset_backup_state(&self) -> Result<(), Self::Error> {
+        self.call(StoreCall::ResetBackupState).await?;
+
+        Ok(())
+    }
+
+    async fn load_backup_keys(&self) -> Result<BackupKeys, Self::Error> {
+        let decryption_key = self
+            .get_custom_string(BACKUP_DECRYPTION_KEY)
+            .await?
+            .map(|key| BackupDecryptionKey::from_base64(&key))
+            .transpose()
+            .map_err(|_| CryptoStoreError::UnpicklingError)?;
+        let backup_version = self.get_custom_string(BACKUP_VERSION).await?;
+
+        Ok(BackupKeys { decryption_key, backup_version })
+    }
+
+    async fn load_dehydrated_device_pickle_key(
+        &self,
+    ) -> Result<Option<DehydratedDeviceKey>, Self::Error> {
+        self.get_custom_string(DEHYDRATED_DEVICE_PICKLE_KEY)
+            .await?
+            .map(|key| DehydratedDeviceKey::from_base64(&key))
+            .transpose()
+            .map_err(|_| CryptoStoreError::UnpicklingError)
+    }
+
+    async fn delete_dehydrated_device_pickle_key(&self) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+        push(&mut js_changes, "customValues", custom_value(DEHYDRATED_DEVICE_PICKLE_KEY, None));
+
+        self.save(js_changes).await
+    }
+
+    async fn get_outbound_group_session(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<OutboundGroupSession>, Self::Error> {
+        let Some(pickle) = self
+            .call_optional::<PickledOutboundGroupSession>(StoreCall::GetOutboundGroupSession {
+                room_id: room_id.to_string(),
+            })
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let account = self.static_account()?;
+
+        Ok(Some(
+            OutboundGroupSession::from_pickle(account.device_id, account.identity_keys, pickle)
+                .map_err(|_| CryptoStoreError::UnpicklingError)?,
+        ))
+    }
+
+    async fn load_tracked_users(&self) -> Result<Vec<TrackedUser>, Self::Error> {
+        self.call_array(StoreCall::LoadTrackedUsers)
+            .await?
+            .into_iter()
+            .map(|mut tracked_user| {
+                Ok(TrackedUser {
+                    user_id: from_value(field(&mut tracked_user, "userId"))?,
+                    dirty: from_value(field(&mut tracked_user, "dirty"))?,
+                })
+            })
+            .collect()
+    }
+
+    async fn save_tracked_users(&self, users: &[(&UserId, bool)]) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+
+        for (user_id, dirty) in users {
+            push(&mut js_changes, "trackedUsers", json!({ "userId": user_id, "dirty": dirty }));
+        }
+
+        self.save(js_changes).await
+    }
+
+    async fn get_device(
+        &self,
+        user_id: &UserId,
+        device_id: &DeviceId,
+    ) -> Result<Option<DeviceData>, Self::Error> {
+        self.call_optional(StoreCall::GetDevice {
+            user_id: user_id.to_string(),
+            device_id: device_id.to_string(),
+        })
+        .await
+    }
+
+    async fn get_user_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<OwnedDeviceId, DeviceData>, Self::Error> {
+        self.call_array(StoreCall::GetUserDevices { user_id: user_id.to_string() })
+            .await?
+            .into_iter()
+            .map(|device| {
+                let device: DeviceData = from_value(device)?;
+
+                Ok((device.device_id().to_owned(), device))
+            })
+            .collect()
+    }
+
+    async fn get_own_device(&self) -> Result<DeviceData, Self::Error> {
+        let account = self.static_account()?;
+
+        self.get_device(&account.user_id, &account.device_id)
+            .await?
+            .ok_or(CryptoStoreError::AccountUnset)
+    }
+
+    async fn get_user_identity(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<UserIdentityData>, Self::Error> {
+        self.call_optional(StoreCall::GetUserIdentity { user_id: user_id.to_string() }).await
+    }
+
+    async fn is_message_known(&self, message_hash: &OlmMessageHash) -> Result<bool, Self::Error> {
+        let known = self
+            .call(StoreCall::IsMessageKnown {
+                sender_key: message_hash.sender_key.clone(),
+                hash: message_hash.hash.clone(),
+            })
+            .await?;
+
+        // Treating a malformed value as an unknown message would allow Olm
+        // messages to be replayed, so fail loudly instead.
+        known.as_bool().ok_or_else(|| {
+            CryptoStoreError::backend(JsStoreError {
+                method: "isMessageKnown",
+                reason: format!("expected a boolean, got `{known}`"),
+            })
+        })
+    }
+
+    async fn get_outgoing_secret_requests(
+        &self,
+        request_id: &TransactionId,
+    ) -> Result<Option<GossipRequest>, Self::Error> {
+        self.call_optional(StoreCall::GetGossipRequest { request_id: request_id.to_string() }).await
+    }
+
+    async fn get_secret_request_by_info(
+        &self,
+        secret_info: &SecretInfo,
+    ) -> Result<Option<GossipRequest>, Self::Error> {
+        self.call_optional(StoreCall::GetGossipRequestByInfo { info_key: secret_info.as_key() })
+            .await
+    }
+
+    async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>, Self::Error> {
+        Ok(self
+            .all_gossip_requests()
+            .await?
+            .into_iter()
+            .filter(|request| !request.sent_out)
+            .collect())
+    }
+
+    async fn delete_outgoing_secret_requests(
+        &self,
+        request_id: &TransactionId,
+    ) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+        push(&mut js_changes, "deletedGossipRequests", json!(request_id));
+
+        self.save(js_changes).await
+    }
+
+    async fn get_secrets_from_inbox(
+        &self,
+        secret_name: &SecretName,
+    ) -> Result<Vec<GossippedSecret>, Self::Error> {
+        self.call_array(StoreCall::GetSecretsFromInbox {
+            secret_name: secret_name.as_str().to_owned(),
+        })
+        .await?
+        .into_iter()
+        .map(from_value)
+        .collect()
+    }
+
+    async fn delete_secrets_from_inbox(&self, secret_name: &SecretName) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+        push(&mut js_changes, "deletedSecrets", json!(secret_name.as_str()));
+
+        self.save(js_changes).await
+    }
+
+    async fn get_room_settings(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<RoomSettings>, Self::Error> {
+        self.call_optional(StoreCall::GetRoomSettings { room_id: room_id.to_string() }).await
+    }
+
+    async fn get_received_room_key_bundle_data(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<StoredRoomKeyBundleData>, Self::Error> {
+        self.call_optional(StoreCall::GetReceivedRoomKeyBundleData {
+            room_id: room_id.to_string(),
+            user_id: user_id.to_string(),
+        })
+        .await
+    }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.call_optional::<String>(StoreCall::GetCustomValue { key: key.to_owned() })
+            .await?
+            .map(base64_decode)
+            .transpose()
+            .map_err(|_| CryptoStoreError::UnpicklingError)
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+        push(&mut js_changes, "customValues", custom_value(key, Some(&value)));
+
+        self.save(js_changes).await
+    }
+
+    async fn remove_custom_value(&self, key: &str) -> Result<(), Self::Error> {
+        let mut js_changes = empty_changes();
+        push(&mut js_changes, "customValues", custom_value(key, None));
+
+        self.save(js_changes).await
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<Option<CrossProcessLockGeneration>, Self::Error> {
+        // The custom store is only shared with the current process, so an
+        // in-memory lease is enough.
+        Ok(try_take_leased_lock(&mut self.leases.write().unwrap(), lease_duration_ms, key, holder))
+    }
+
+    async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
+        self.get_custom_string(NEXT_BATCH_TOKEN).await
+    }
+
+    async fn clear_caches(&self) {
+        // Nothing is cached by this store.
+    }
+
+    async fn get_size(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+}
@@ -1,9 +1,9 @@
 //! `GET /_matrix/client/*/sync`.
 
-use matrix_sdk_common::ruma;
+use matrix_sdk_common::{deserialized_responses, ruma};
 use napi_derive::*;
 
-use crate::identifiers;
+use crate::{encryption, identifiers, vodozemac};
 
 /// Information on E2E device updates.
 #[napi]
@@ -48,3 +48,156 @@ impl DeviceLists {
         self.inner.left.iter().map(|user| identifiers::UserId::from(user.to_owned())).collect()
     }
 }
+
+/// The result of `OlmMachine.receiveSyncChanges`.
+#[napi]
+pub struct SyncChangesResult {
+    pub(crate) to_device_events: Vec<ProcessedToDeviceEvent>,
+    pub(crate) room_key_infos: Vec<RoomKeyInfo>,
+}
+
+#[napi]
+impl SyncChangesResult {
+    /// The to-device events of the sync response, once processed.
+    #[napi(getter)]
+    pub fn to_device_events(&self) -> Vec<ProcessedToDeviceEvent> {
+        self.to_device_events.clone()
+    }
+
+    /// Information about the room keys received as part of the
+    /// to-device events.
+    #[napi(getter)]
+    pub fn room_key_infos(&self) -> Vec<RoomKeyInfo> {
+        self.room_key_infos.clone()
+    }
+}
+
+/// The type of a `ProcessedToDeviceEvent`.
+#[napi]
+pub enum ProcessedToDeviceEventType {
+    /// The event was encrypted, and has been successfully decrypted.
+    Decrypted,
+
+    /// The event was not encrypted.
+    PlainText,
+
+    /// The event was encrypted, and could not be decrypted.
+    UnableToDecrypt,
+
+    /// The event is invalid, i.e. it could not be deserialized.
+    Invalid,
+}
+
+/// A to-device event, as processed by `OlmMachine.receiveSyncChanges`.
+#[napi]
+#[derive(Clone)]
+pub struct ProcessedToDeviceEvent {
+    /// How the event has been processed.
+    #[napi(readonly, js_name = "type")]
+    pub processed_type: ProcessedToDeviceEventType,
+
+    /// A JSON-encoded string containing the event: the decrypted event
+    /// if it has been decrypted, the event as it has been received
+    /// otherwise.
+    #[napi(readonly)]
+    pub raw_event: String,
+}
+
+impl From<deserialized_responses::ProcessedToDeviceEvent> for ProcessedToDeviceEvent {
+    fn from(value: deserialized_responses::ProcessedToDeviceEvent) -> Self {
+        use deserialized_responses::ProcessedToDeviceEvent::*;
+
+        let processed_type = match &value {
+            Decrypted { .. } => ProcessedToDeviceEventType::Decrypted,
+            PlainText { .. } => ProcessedToDeviceEventType::PlainText,
+            UnableToDecrypt { .. } => ProcessedToDeviceEventType::UnableToDecrypt,
+            Invalid { .. } => ProcessedToDeviceEventType::Invalid,
+        };
+
+        Self { processed_type, raw_event: value.to_raw().json().get().to_owned() }
+    }
+}
+
+/// Information about a room key that has been received or imported.
+#[napi]
+#[derive(Clone)]
+pub struct RoomKeyInfo {
+    pub(crate) inner: matrix_sdk_crypto::store::types::RoomKeyInfo,
+}
+
+#[napi]
+impl RoomKeyInfo {
+    /// The encryption algorithm the key is used with.
+    ///
+    /// Throws if the algorithm is unknown to this binding.
+    #[napi(getter)]
+    pub fn algorithm(&self) -> napi::Result<encryption::EncryptionAlgorithm> {
+        self.inner.algorithm.clone().try_into()
+    }
+
+    /// The room where the key is used.
+    #[napi(getter)]
+    pub fn room_id(&self) -> identifiers::RoomId {
+        identifiers::RoomId::from(self.inner.room_id.clone())
+    }
+
+    /// The Curve25519 key of the device which initiated the session
+    /// originally.
+    #[napi(getter)]
+    pub fn sender_key(&self) -> vodozemac::Curve25519PublicKey {
+        self.inner.sender_key.into()
+    }
+
+    /// The ID of the session that the key is for.
+    #[napi(getter)]
+    pub fn session_id(&self) -> String {
+        self.inner.session_id.clone()
+    }
+}
+
+impl From<matrix_sdk_crypto::store::types::RoomKeyInfo> for RoomKeyInfo {
+    fn from(inner: matrix_sdk_crypto::store::types::RoomKeyInfo) -> Self {
+        Self { inner }
+    }
+}
+
+/// Information about a room key that has been withheld by its sender.
+#[napi]
+#[derive(Clone)]
+pub struct RoomKeyWithheldInfo {
+    pub(crate) inner: matrix_sdk_crypto::store::types::RoomKeyWithheldInfo,
+}
+
+#[napi]
+impl RoomKeyWithheldInfo {
+    /// The user ID of the user that sent us the withheld notification.
+    #[napi(getter)]
+    pub fn sender(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.withheld_event.sender.clone())
+    }
+
+    /// The room ID of the room the withheld room key belongs to.
+    #[napi(getter)]
+    pub fn room_id(&self) -> identifiers::RoomId {
+        identifiers::RoomId::from(self.inner.room_id.clone())
+    }
+
+    /// The ID of the session that the withheld room key belongs to.
+    #[napi(getter)]
+    pub fn session_id(&self) -> String {
+        self.inner.session_id.clone()
+    }
+
+    /// The reason why the room key has been withheld, e.g.
+    /// `m.unverified`.
+    #[napi(getter)]
+    pub fn withheld_code(&self) -> String {
+        self.inner.withheld_event.content.withheld_code().as_str().to_owned()
+    }
+}
+
+impl From<matrix_sdk_crypto::store::types::RoomKeyWithheldInfo> for RoomKeyWithheldInfo {
+    fn from(inner: matrix_sdk_crypto::store::types::RoomKeyWithheldInfo) -> Self {
+        Self { inner }
+    }
+}
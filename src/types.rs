@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use matrix_sdk_crypto::backups::{
     SignatureState as InnerSignatureState, SignatureVerification as InnerSignatureVerification,
@@ -6,7 +6,7 @@ use matrix_sdk_crypto::backups::{
 use napi_derive::*;
 
 use crate::{
-    identifiers::{DeviceKeyId, UserId},
+    identifiers::{DeviceKeyId, RoomId, UserId},
     into_err,
     vodozemac::Ed25519Signature,
 };
@@ -228,3 +228,68 @@ impl SignatureVerification {
         self.inner.trusted()
     }
 }
+
+/// The result of a call to `OlmMachine.importRoomKeys` or similar.
+#[napi]
+#[derive(Debug)]
+pub struct RoomKeyImportResult {
+    /// The number of room keys that were imported.
+    #[napi(readonly)]
+    pub imported_count: u32,
+
+    /// The total number of room keys that were found in the export.
+    #[napi(readonly)]
+    pub total_count: u32,
+
+    /// The number of room keys that were skipped because they could not be
+    /// read, e.g. backed up room keys that could not be decrypted with the
+    /// backup decryption key. They are not included in `total_count`.
+    #[napi(readonly)]
+    pub failed_count: u32,
+
+    /// The map of keys that were imported.
+    ///
+    /// A map from room ID to a map of sender key to a set of session IDs.
+    keys: BTreeMap<matrix_sdk_common::ruma::OwnedRoomId, BTreeMap<String, BTreeSet<String>>>,
+}
+
+#[napi]
+impl RoomKeyImportResult {
+    /// The keys that were imported, as a map from room ID to a map of
+    /// sender key to an array of session IDs.
+    #[napi]
+    pub fn keys(&self) -> HashMap<String, HashMap<String, Vec<String>>> {
+        self.keys
+            .iter()
+            .map(|(room_id, room_keys)| {
+                (
+                    room_id.to_string(),
+                    room_keys
+                        .iter()
+                        .map(|(sender_key, session_ids)| {
+                            (sender_key.clone(), session_ids.iter().cloned().collect())
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// The IDs of the rooms for which room keys were imported.
+    #[napi]
+    pub fn room_ids(&self) -> Vec<RoomId> {
+        self.keys.keys().map(|room_id| room_id.clone().into()).collect()
+    }
+}
+
+impl From<matrix_sdk_crypto::RoomKeyImportResult> for RoomKeyImportResult {
+    fn from(value: matrix_sdk_crypto::RoomKeyImportResult) -> Self {
+        RoomKeyImportResult {
+            // `napi` cannot represent an `usize`, so saturate the counts into u32s.
+            imported_count: value.imported_count.try_into().unwrap_or(u32::MAX),
+            total_count: value.total_count.try_into().unwrap_or(u32::MAX),
+            failed_count: 0,
+            keys: value.keys,
+        }
+    }
+}
@@ -0,0 +1,826 @@
+//! Interactive verification of devices, i.e. the `m.key.verification.*`
+//! flows.
+
+#[cfg(feature = "qrcode")]
+use std::ops::Deref;
+
+use matrix_sdk_common::ruma::events::key::verification::VerificationMethod as RumaVerificationMethod;
+#[cfg(feature = "qrcode")]
+use matrix_sdk_qrcode::QrVerificationData;
+use napi::bindgen_prelude::Either;
+#[cfg(feature = "qrcode")]
+use napi::bindgen_prelude::Uint8Array;
+use napi_derive::*;
+
+use crate::{
+    identifiers, into_err,
+    requests::{self, RoomMessageRequest, ToDeviceRequest},
+};
+
+/// List of available verification methods.
+#[napi]
+pub enum VerificationMethod {
+    /// The `m.sas.v1` verification method.
+    ///
+    /// SAS means Short Authentication String.
+    SasV1,
+
+    /// The `m.qr_code.scan.v1` verification method.
+    QrCodeScanV1,
+
+    /// The `m.qr_code.show.v1` verification method.
+    QrCodeShowV1,
+
+    /// The `m.reciprocate.v1` verification method.
+    ReciprocateV1,
+}
+
+impl From<VerificationMethod> for RumaVerificationMethod {
+    fn from(value: VerificationMethod) -> Self {
+        use VerificationMethod::*;
+
+        match value {
+            SasV1 => Self::SasV1,
+            QrCodeScanV1 => Self::QrCodeScanV1,
+            QrCodeShowV1 => Self::QrCodeShowV1,
+            ReciprocateV1 => Self::ReciprocateV1,
+        }
+    }
+}
+
+impl TryFrom<RumaVerificationMethod> for VerificationMethod {
+    type Error = napi::Error;
+
+    fn try_from(value: RumaVerificationMethod) -> Result<Self, Self::Error> {
+        use RumaVerificationMethod::*;
+
+        Ok(match value {
+            SasV1 => Self::SasV1,
+            QrCodeScanV1 => Self::QrCodeScanV1,
+            QrCodeShowV1 => Self::QrCodeShowV1,
+            ReciprocateV1 => Self::ReciprocateV1,
+            _ => {
+                return Err(napi::Error::from_reason(format!(
+                    "Unknown verification method `{value}`"
+                )))
+            }
+        })
+    }
+}
+
+/// Convert a list of verification methods coming from the Rust SDK,
+/// silently ignoring the ones this binding doesn't know about.
+fn verification_methods_from(methods: Vec<RumaVerificationMethod>) -> Vec<VerificationMethod> {
+    methods.into_iter().filter_map(|method| method.try_into().ok()).collect()
+}
+
+/// Convert an outgoing verification request from the Rust SDK into a
+/// `ToDeviceRequest` or a `RoomMessageRequest`.
+fn outgoing_verification_request(
+    request: matrix_sdk_crypto::types::requests::OutgoingVerificationRequest,
+) -> napi::Result<Either<ToDeviceRequest, RoomMessageRequest>> {
+    use matrix_sdk_crypto::types::requests::OutgoingVerificationRequest::*;
+
+    let request_id = request.request_id().to_string();
+
+    Ok(match request {
+        ToDevice(request) => Either::A(ToDeviceRequest::try_from((request_id, &request))?),
+        InRoom(request) => Either::B(RoomMessageRequest::try_from((request_id, &request))?),
+    })
+}
+
+/// An object controlling key verification requests.
+///
+/// Interactive verification flows usually start with a verification
+/// request, this object lets you send and reply to such a verification
+/// request.
+///
+/// After the initial handshake the verification flow transitions into
+/// one of the verification methods, e.g. SAS with `start_sas`, or QR
+/// codes with `generate_qr_code` and `scan_qr_code` when the `qrcode`
+/// feature is enabled.
+#[napi]
+#[derive(Clone)]
+pub struct VerificationRequest {
+    pub(crate) inner: matrix_sdk_crypto::VerificationRequest,
+}
+
+impl From<matrix_sdk_crypto::VerificationRequest> for VerificationRequest {
+    fn from(inner: matrix_sdk_crypto::VerificationRequest) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl VerificationRequest {
+    /// Our own user ID.
+    #[napi(getter)]
+    pub fn own_user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.own_user_id().to_owned())
+    }
+
+    /// The ID of the other user that is participating in this
+    /// verification request.
+    #[napi(getter)]
+    pub fn other_user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.other_user().to_owned())
+    }
+
+    /// The ID of the other device that is participating in this
+    /// verification.
+    #[napi(getter)]
+    pub fn other_device_id(&self) -> Option<identifiers::DeviceId> {
+        self.inner.other_device_id().map(Into::into)
+    }
+
+    /// Get the room ID if the verification is happening inside a
+    /// room.
+    #[napi(getter)]
+    pub fn room_id(&self) -> Option<identifiers::RoomId> {
+        self.inner.room_id().map(|room_id| room_id.to_owned().into())
+    }
+
+    /// Get the unique ID of this verification request.
+    #[napi(getter)]
+    pub fn flow_id(&self) -> String {
+        self.inner.flow_id().as_str().to_owned()
+    }
+
+    /// Get info about the cancellation if the verification request
+    /// has been cancelled.
+    #[napi(getter)]
+    pub fn cancel_info(&self) -> Option<CancelInfo> {
+        self.inner.cancel_info().map(Into::into)
+    }
+
+    /// Has the verification request been answered by another device?
+    #[napi(getter)]
+    pub fn is_passive(&self) -> bool {
+        self.inner.is_passive()
+    }
+
+    /// Is the verification request ready to start a verification
+    /// flow?
+    #[napi(getter)]
+    pub fn is_ready(&self) -> bool {
+        self.inner.is_ready()
+    }
+
+    /// Has the verification flow timed out?
+    #[napi(getter)]
+    pub fn timed_out(&self) -> bool {
+        self.inner.timed_out()
+    }
+
+    /// Has the verification flow been completed?
+    #[napi(getter)]
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Has the verification flow been cancelled?
+    #[napi(getter)]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Did we initiate the verification request?
+    #[napi(getter)]
+    pub fn we_started(&self) -> bool {
+        self.inner.we_started()
+    }
+
+    /// Is this a verification that is verifying one of our own
+    /// devices?
+    #[napi(getter)]
+    pub fn is_self_verification(&self) -> bool {
+        self.inner.is_self_verification()
+    }
+
+    /// The number of milliseconds remaining before this verification
+    /// flow times out.
+    #[napi(getter)]
+    pub fn time_remaining_millis(&self) -> f64 {
+        self.inner.time_remaining().as_millis() as f64
+    }
+
+    /// Get the supported verification methods of the other side.
+    ///
+    /// Will be present only if the other side requested the
+    /// verification or if we’re in the ready state.
+    #[napi(getter)]
+    pub fn their_supported_methods(&self) -> Option<Vec<VerificationMethod>> {
+        self.inner.their_supported_methods().map(verification_methods_from)
+    }
+
+    /// Get our own supported verification methods that we advertised.
+    ///
+    /// Will be present only we requested the verification or if
+    /// we’re in the ready state.
+    #[napi(getter)]
+    pub fn our_supported_methods(&self) -> Option<Vec<VerificationMethod>> {
+        self.inner.our_supported_methods().map(verification_methods_from)
+    }
+
+    /// Get the current phase of this request.
+    #[napi(getter)]
+    pub fn phase(&self) -> VerificationRequestPhase {
+        self.inner.state().into()
+    }
+
+    /// Accept the verification request signaling that our client
+    /// supports the given verification methods.
+    ///
+    /// If `methods` is not set, the default methods of the Rust SDK
+    /// are advertised.
+    ///
+    /// Returns either a `ToDeviceRequest` or a `RoomMessageRequest`
+    /// that needs to be sent out, or `null` if the request cannot be
+    /// accepted anymore.
+    #[napi(strict)]
+    pub fn accept(
+        &self,
+        methods: Option<Vec<VerificationMethod>>,
+    ) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        match methods {
+            Some(methods) => {
+                self.inner.accept_with_methods(methods.into_iter().map(Into::into).collect())
+            }
+            None => self.inner.accept(),
+        }
+        .map(outgoing_verification_request)
+        .transpose()
+    }
+
+    /// Cancel the verification request.
+    ///
+    /// Returns either a `ToDeviceRequest` or a `RoomMessageRequest`
+    /// that needs to be sent out, or `null` if the request is already
+    /// done or cancelled.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        self.inner.cancel().map(outgoing_verification_request).transpose()
+    }
+
+    /// Transition from this verification request into a SAS
+    /// verification flow.
+    ///
+    /// Returns `null` if the verification request isn't ready yet, or
+    /// if the other side doesn't support SAS.
+    #[napi]
+    pub async fn start_sas(&self) -> napi::Result<Option<StartSasResult>> {
+        let Some((sas, request)) = self.inner.start_sas().await.map_err(into_err)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(StartSasResult {
+            sas: sas.into(),
+            request: outgoing_verification_request(request)?,
+        }))
+    }
+}
+
+#[cfg(feature = "qrcode")]
+#[napi]
+impl VerificationRequest {
+    /// Generate a QR code that can be used by another client to start
+    /// a QR code based verification.
+    ///
+    /// Returns `null` if the verification request isn't ready yet, or
+    /// if the other side doesn't support scanning QR codes.
+    #[napi]
+    pub async fn generate_qr_code(&self) -> napi::Result<Option<Qr>> {
+        Ok(self.inner.generate_qr_code().await.map_err(into_err)?.map(Into::into))
+    }
+
+    /// Start a QR code verification by providing a scanned QR code
+    /// for this verification flow.
+    ///
+    /// Returns `null` if the verification request isn't ready yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`, the raw bytes of the QR code that was scanned.
+    #[napi(strict)]
+    pub async fn scan_qr_code(&self, data: Uint8Array) -> napi::Result<Option<Qr>> {
+        let data = QrVerificationData::from_bytes(data.deref()).map_err(into_err)?;
+
+        Ok(self.inner.scan_qr_code(data).await.map_err(into_err)?.map(Into::into))
+    }
+}
+
+/// The result of `VerificationRequest.startSas`.
+#[napi]
+pub struct StartSasResult {
+    /// The SAS verification object.
+    #[napi(readonly)]
+    pub sas: Sas,
+
+    /// The request that needs to be sent out to start the SAS
+    /// verification flow, either a `ToDeviceRequest` or a
+    /// `RoomMessageRequest`.
+    #[napi(readonly)]
+    pub request: Either<ToDeviceRequest, RoomMessageRequest>,
+}
+
+/// The result of `OlmMachine.requestVerification`.
+#[napi]
+pub struct RequestVerificationResult {
+    /// The verification request object.
+    #[napi(readonly)]
+    pub verification_request: VerificationRequest,
+
+    /// The request that needs to be sent out to notify the other
+    /// device about the verification request, either a
+    /// `ToDeviceRequest` or a `RoomMessageRequest`.
+    #[napi(readonly)]
+    pub request: Either<ToDeviceRequest, RoomMessageRequest>,
+}
+
+impl
+    TryFrom<(
+        matrix_sdk_crypto::VerificationRequest,
+        matrix_sdk_crypto::types::requests::OutgoingVerificationRequest,
+    )> for RequestVerificationResult
+{
+    type Error = napi::Error;
+
+    fn try_from(
+        (verification_request, request): (
+            matrix_sdk_crypto::VerificationRequest,
+            matrix_sdk_crypto::types::requests::OutgoingVerificationRequest,
+        ),
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            verification_request: verification_request.into(),
+            request: outgoing_verification_request(request)?,
+        })
+    }
+}
+
+/// The phase of a verification request.
+#[napi]
+pub enum VerificationRequestPhase {
+    /// The verification request has been newly created by us.
+    Created,
+
+    /// The verification request was received from the other party.
+    Requested,
+
+    /// The verification request was accepted and we're ready to start
+    /// a verification flow.
+    Ready,
+
+    /// The verification request transitioned into a concrete
+    /// verification flow, e.g. SAS.
+    Transitioned,
+
+    /// The verification flow that was started with this request has
+    /// finished.
+    Done,
+
+    /// The verification process has been cancelled.
+    Cancelled,
+}
+
+impl From<matrix_sdk_crypto::VerificationRequestState> for VerificationRequestPhase {
+    fn from(value: matrix_sdk_crypto::VerificationRequestState) -> Self {
+        use matrix_sdk_crypto::VerificationRequestState::*;
+
+        match value {
+            Created { .. } => Self::Created,
+            Requested { .. } => Self::Requested,
+            Ready { .. } => Self::Ready,
+            Transitioned { .. } => Self::Transitioned,
+            Done => Self::Done,
+            Cancelled(_) => Self::Cancelled,
+        }
+    }
+}
+
+/// Short Authentication String (SAS) verification object.
+///
+/// It is used to verify a device interactively, by comparing emojis
+/// or decimals on both devices.
+#[napi]
+#[derive(Clone)]
+pub struct Sas {
+    pub(crate) inner: matrix_sdk_crypto::Sas,
+}
+
+impl From<matrix_sdk_crypto::Sas> for Sas {
+    fn from(inner: matrix_sdk_crypto::Sas) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl Sas {
+    /// Get our own user ID.
+    #[napi(getter)]
+    pub fn user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.user_id().to_owned())
+    }
+
+    /// Get our own device ID.
+    #[napi(getter)]
+    pub fn device_id(&self) -> identifiers::DeviceId {
+        identifiers::DeviceId::from(self.inner.device_id().to_owned())
+    }
+
+    /// Get the user ID of the other side.
+    #[napi(getter)]
+    pub fn other_user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.other_user_id().to_owned())
+    }
+
+    /// Get the device ID of the other side.
+    #[napi(getter)]
+    pub fn other_device_id(&self) -> identifiers::DeviceId {
+        identifiers::DeviceId::from(self.inner.other_device_id().to_owned())
+    }
+
+    /// Get the unique ID that identifies this SAS verification flow.
+    #[napi(getter)]
+    pub fn flow_id(&self) -> String {
+        self.inner.flow_id().as_str().to_owned()
+    }
+
+    /// Get the room ID if the verification is happening inside a
+    /// room.
+    #[napi(getter)]
+    pub fn room_id(&self) -> Option<identifiers::RoomId> {
+        self.inner.room_id().map(|room_id| room_id.to_owned().into())
+    }
+
+    /// Does this verification flow support displaying emoji for the
+    /// short authentication string?
+    #[napi(getter)]
+    pub fn supports_emoji(&self) -> bool {
+        self.inner.supports_emoji()
+    }
+
+    /// Did this verification flow start from a verification request?
+    #[napi(getter)]
+    pub fn started_from_request(&self) -> bool {
+        self.inner.started_from_request()
+    }
+
+    /// Is this a verification that is verifying one of our own
+    /// devices?
+    #[napi(getter)]
+    pub fn is_self_verification(&self) -> bool {
+        self.inner.is_self_verification()
+    }
+
+    /// Have we confirmed that the short auth string matches?
+    #[napi(getter)]
+    pub fn have_we_confirmed(&self) -> bool {
+        self.inner.have_we_confirmed()
+    }
+
+    /// Has the verification been accepted by both parties?
+    #[napi(getter)]
+    pub fn has_been_accepted(&self) -> bool {
+        self.inner.has_been_accepted()
+    }
+
+    /// Get info about the cancellation if the verification flow has
+    /// been cancelled.
+    #[napi(getter)]
+    pub fn cancel_info(&self) -> Option<CancelInfo> {
+        self.inner.cancel_info().map(Into::into)
+    }
+
+    /// Did we initiate the verification flow?
+    #[napi(getter)]
+    pub fn we_started(&self) -> bool {
+        self.inner.we_started()
+    }
+
+    /// Has the SAS verification flow timed out?
+    #[napi(getter)]
+    pub fn timed_out(&self) -> bool {
+        self.inner.timed_out()
+    }
+
+    /// Are we in a state where we can show the short auth string?
+    #[napi(getter)]
+    pub fn can_be_presented(&self) -> bool {
+        self.inner.can_be_presented()
+    }
+
+    /// Is the SAS flow done?
+    #[napi(getter)]
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Is the SAS flow cancelled?
+    #[napi(getter)]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Accept the SAS verification.
+    ///
+    /// This does nothing (and returns `null`) if the verification was
+    /// already accepted, otherwise it returns either a
+    /// `ToDeviceRequest` or a `RoomMessageRequest` that needs to be
+    /// sent out.
+    #[napi]
+    pub fn accept(&self) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        self.inner.accept().map(outgoing_verification_request).transpose()
+    }
+
+    /// Confirm the SAS verification.
+    ///
+    /// This confirms that the short auth strings match on both sides.
+    ///
+    /// Does nothing if we’re not in a state where we can confirm the
+    /// short auth string.
+    #[napi]
+    pub async fn confirm(&self) -> napi::Result<ConfirmVerificationResult> {
+        let (outgoing_requests, signature_upload_request) =
+            self.inner.confirm().await.map_err(into_err)?;
+
+        Ok(ConfirmVerificationResult {
+            requests: outgoing_requests
+                .into_iter()
+                .map(outgoing_verification_request)
+                .collect::<napi::Result<_>>()?,
+            signature_upload_request: signature_upload_request
+                .as_ref()
+                .map(requests::SignatureUploadRequest::try_from)
+                .transpose()?,
+        })
+    }
+
+    /// Cancel the verification.
+    ///
+    /// Returns either a `ToDeviceRequest` or a `RoomMessageRequest`
+    /// that needs to be sent out, or `null` if the flow is already
+    /// done or cancelled.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        self.inner.cancel().map(outgoing_verification_request).transpose()
+    }
+
+    /// Get the emoji version of the short auth string.
+    ///
+    /// Returns `null` if we can't yet present the short auth string,
+    /// otherwise seven `Emoji` objects.
+    #[napi]
+    pub fn emoji(&self) -> Option<Vec<Emoji>> {
+        Some(self.inner.emoji()?.into_iter().map(Into::into).collect())
+    }
+
+    /// Get the index of the emoji representing the short auth string.
+    ///
+    /// Returns `null` if we can't yet present the short auth string,
+    /// otherwise seven `u8` numbers in the range from 0 to 63
+    /// inclusive which can be converted to an emoji using [the
+    /// relevant specification
+    /// entry](https://spec.matrix.org/unstable/client-server-api/#sas-method-emoji).
+    #[napi]
+    pub fn emoji_index(&self) -> Option<Vec<u32>> {
+        Some(self.inner.emoji_index()?.into_iter().map(Into::into).collect())
+    }
+
+    /// Get the decimal version of the short auth string.
+    ///
+    /// Returns `null` if we can't yet present the short auth string,
+    /// otherwise a tuple containing three 4-digit integers that
+    /// represent the short auth string.
+    #[napi]
+    pub fn decimals(&self) -> Option<Vec<u32>> {
+        let (first, second, third) = self.inner.decimals()?;
+
+        Some(vec![first.into(), second.into(), third.into()])
+    }
+}
+
+/// The result of `Sas.confirm`.
+#[napi]
+pub struct ConfirmVerificationResult {
+    /// The requests that need to be sent out to notify the other side
+    /// about the confirmation, each being either a `ToDeviceRequest`
+    /// or a `RoomMessageRequest`.
+    #[napi(readonly)]
+    pub requests: Vec<Either<ToDeviceRequest, RoomMessageRequest>>,
+
+    /// An optional request to upload the signatures that the
+    /// verification produced.
+    #[napi(readonly)]
+    pub signature_upload_request: Option<requests::SignatureUploadRequest>,
+}
+
+/// QR code based verification object.
+///
+/// It is used to verify a device interactively, by scanning a QR code
+/// with the other device's camera.
+#[cfg(feature = "qrcode")]
+#[napi]
+#[derive(Clone)]
+pub struct Qr {
+    pub(crate) inner: matrix_sdk_crypto::QrVerification,
+}
+
+#[cfg(feature = "qrcode")]
+impl From<matrix_sdk_crypto::QrVerification> for Qr {
+    fn from(inner: matrix_sdk_crypto::QrVerification) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "qrcode")]
+#[napi]
+impl Qr {
+    /// Get our own user ID.
+    #[napi(getter)]
+    pub fn user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.user_id().to_owned())
+    }
+
+    /// Get the user ID of the other side.
+    #[napi(getter)]
+    pub fn other_user_id(&self) -> identifiers::UserId {
+        identifiers::UserId::from(self.inner.other_user_id().to_owned())
+    }
+
+    /// Get the device ID of the other side.
+    #[napi(getter)]
+    pub fn other_device_id(&self) -> identifiers::DeviceId {
+        identifiers::DeviceId::from(self.inner.other_device_id().to_owned())
+    }
+
+    /// Get the unique ID that identifies this QR code verification
+    /// flow.
+    #[napi(getter)]
+    pub fn flow_id(&self) -> String {
+        self.inner.flow_id().as_str().to_owned()
+    }
+
+    /// Get the room ID if the verification is happening inside a
+    /// room.
+    #[napi(getter)]
+    pub fn room_id(&self) -> Option<identifiers::RoomId> {
+        self.inner.room_id().map(|room_id| room_id.to_owned().into())
+    }
+
+    /// Has the QR verification been scanned by the other side?
+    ///
+    /// When the verification object is in this state it’s required
+    /// that the user confirms that the other side has scanned the QR
+    /// code, with `confirm_scanning`.
+    #[napi(getter)]
+    pub fn has_been_scanned(&self) -> bool {
+        self.inner.has_been_scanned()
+    }
+
+    /// Has the scanning of the QR code been confirmed by us?
+    #[napi(getter)]
+    pub fn has_been_confirmed(&self) -> bool {
+        self.inner.has_been_confirmed()
+    }
+
+    /// Have we successfully scanned the QR code and are able to send
+    /// a reciprocation event?
+    #[napi(getter)]
+    pub fn reciprocated(&self) -> bool {
+        self.inner.reciprocated()
+    }
+
+    /// Is this a verification that is verifying one of our own
+    /// devices?
+    #[napi(getter)]
+    pub fn is_self_verification(&self) -> bool {
+        self.inner.is_self_verification()
+    }
+
+    /// Did we initiate the verification flow?
+    #[napi(getter)]
+    pub fn we_started(&self) -> bool {
+        self.inner.we_started()
+    }
+
+    /// Get info about the cancellation if the verification flow has
+    /// been cancelled.
+    #[napi(getter)]
+    pub fn cancel_info(&self) -> Option<CancelInfo> {
+        self.inner.cancel_info().map(Into::into)
+    }
+
+    /// Is the QR code verification done?
+    #[napi(getter)]
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Has the verification flow been cancelled?
+    #[napi(getter)]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Generate the raw bytes of the QR code, to be rendered by the
+    /// caller as a QR code image.
+    #[napi]
+    pub fn to_bytes(&self) -> napi::Result<Uint8Array> {
+        Ok(Uint8Array::new(self.inner.to_bytes().map_err(into_err)?))
+    }
+
+    /// Notify the other side that we have successfully scanned the QR
+    /// code and that the QR verification flow can start.
+    ///
+    /// This will return either a `ToDeviceRequest` or a
+    /// `RoomMessageRequest` that needs to be sent out, or `null` if
+    /// the QR code has been displayed by us rather than scanned.
+    #[napi]
+    pub fn reciprocate(&self) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        self.inner.reciprocate().map(outgoing_verification_request).transpose()
+    }
+
+    /// Confirm that the other side has scanned our QR code.
+    ///
+    /// Returns either a `ToDeviceRequest` or a `RoomMessageRequest`
+    /// that needs to be sent out, or `null` if the QR code hasn't
+    /// been scanned yet.
+    #[napi]
+    pub fn confirm_scanning(
+        &self,
+    ) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        self.inner.confirm_scanning().map(outgoing_verification_request).transpose()
+    }
+
+    /// Cancel the verification flow.
+    ///
+    /// Returns either a `ToDeviceRequest` or a `RoomMessageRequest`
+    /// that needs to be sent out, or `null` if the flow is already
+    /// done or cancelled.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<Option<Either<ToDeviceRequest, RoomMessageRequest>>> {
+        self.inner.cancel().map(outgoing_verification_request).transpose()
+    }
+}
+
+/// An emoji that is used for interactive verification using a short
+/// auth string.
+///
+/// This will contain a single emoji and description from the list of
+/// emojis from [the specification].
+///
+/// [the specification]: https://spec.matrix.org/unstable/client-server-api/#sas-method-emoji
+#[napi]
+#[derive(Clone)]
+pub struct Emoji {
+    /// The emoji symbol that represents a part of the short auth
+    /// string, for example: 🐶
+    #[napi(readonly)]
+    pub symbol: String,
+
+    /// The description of the emoji, for example ‘Dog’.
+    #[napi(readonly)]
+    pub description: String,
+}
+
+impl From<matrix_sdk_crypto::Emoji> for Emoji {
+    fn from(value: matrix_sdk_crypto::Emoji) -> Self {
+        Self { symbol: value.symbol.to_owned(), description: value.description.to_owned() }
+    }
+}
+
+/// Information about the cancellation of a verification request or
+/// verification flow.
+#[napi]
+pub struct CancelInfo {
+    inner: matrix_sdk_crypto::CancelInfo,
+}
+
+impl From<matrix_sdk_crypto::CancelInfo> for CancelInfo {
+    fn from(inner: matrix_sdk_crypto::CancelInfo) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl CancelInfo {
+    /// Get the human readable reason of the cancellation.
+    #[napi(getter)]
+    pub fn reason(&self) -> String {
+        self.inner.reason().to_owned()
+    }
+
+    /// Get the `CancelCode` that cancelled this verification, as a
+    /// string, e.g. `m.user`.
+    #[napi(getter)]
+    pub fn cancel_code(&self) -> String {
+        self.inner.cancel_code().to_string()
+    }
+
+    /// Was the verification cancelled by us?
+    #[napi(getter)]
+    pub fn cancelled_by_us(&self) -> bool {
+        self.inner.cancelled_by_us()
+    }
+}
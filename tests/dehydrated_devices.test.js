@@ -0,0 +1,93 @@
+const {
+    DehydratedDevice,
+    DehydratedDeviceKey,
+    DehydratedDevices,
+    DeviceId,
+    OlmMachine,
+    PutDehydratedDeviceRequest,
+    RehydratedDevice,
+    UserId,
+} = require("../");
+
+describe(DehydratedDeviceKey.name, () => {
+    test("can create a random key", () => {
+        const key = DehydratedDeviceKey.createRandomKey();
+
+        expect(key).toBeInstanceOf(DehydratedDeviceKey);
+        expect(Buffer.from(key.toBase64(), "base64")).toHaveLength(32);
+    });
+
+    test("can create a key from an array", () => {
+        const array = new Uint8Array(32).fill(42);
+        const key = DehydratedDeviceKey.createKeyFromArray(array);
+
+        expect(key.toBase64()).toStrictEqual(Buffer.from(array).toString("base64").replace(/=+$/, ""));
+    });
+
+    test("cannot create a key from an array of the wrong length", () => {
+        expect(() => DehydratedDeviceKey.createKeyFromArray(new Uint8Array(31))).toThrow();
+    });
+});
+
+describe(DehydratedDevices.name, () => {
+    const user = new UserId("@alice:example.org");
+
+    test("can create, upload and rehydrate a dehydrated device", async () => {
+        const m = await OlmMachine.initialize(user, new DeviceId("ALICEDEVICE"));
+        const dehydratedDevices = m.dehydratedDevices();
+        expect(dehydratedDevices).toBeInstanceOf(DehydratedDevices);
+
+        const dehydratedDevice = await dehydratedDevices.create();
+        expect(dehydratedDevice).toBeInstanceOf(DehydratedDevice);
+
+        const key = DehydratedDeviceKey.createRandomKey();
+        const request = await dehydratedDevice.keysForUpload("Dehydrated device", key);
+        expect(request).toBeInstanceOf(PutDehydratedDeviceRequest);
+
+        const body = JSON.parse(request.body);
+        expect(body.initial_device_display_name).toStrictEqual("Dehydrated device");
+        expect(body.device_keys.device_id).toStrictEqual(body.device_id);
+        expect(body.device_data).toBeDefined();
+        expect(body.one_time_keys).toBeDefined();
+
+        const other = await OlmMachine.initialize(user, new DeviceId("OTHERDEVICE"));
+        const rehydratedDevice = await other
+            .dehydratedDevices()
+            .rehydrate(key, new DeviceId(body.device_id), JSON.stringify(body.device_data));
+        expect(rehydratedDevice).toBeInstanceOf(RehydratedDevice);
+
+        expect(await rehydratedDevice.receiveEvents("[]")).toStrictEqual([]);
+    });
+
+    test("cannot rehydrate a dehydrated device with the wrong key", async () => {
+        const m = await OlmMachine.initialize(user, new DeviceId("ALICEDEVICE"));
+        const dehydratedDevice = await m.dehydratedDevices().create();
+        const key = DehydratedDeviceKey.createRandomKey();
+        const request = await dehydratedDevice.keysForUpload("Dehydrated device", key);
+        const body = JSON.parse(request.body);
+
+        await expect(
+            m
+                .dehydratedDevices()
+                .rehydrate(
+                    DehydratedDeviceKey.createRandomKey(),
+                    new DeviceId(body.device_id),
+                    JSON.stringify(body.device_data),
+                ),
+        ).rejects.toThrow();
+    });
+
+    test("can save, read and delete the dehydrated device key", async () => {
+        const m = await OlmMachine.initialize(user, new DeviceId("ALICEDEVICE"));
+        const dehydratedDevices = m.dehydratedDevices();
+
+        expect(await dehydratedDevices.getDehydratedDeviceKey()).toBeNull();
+
+        const key = DehydratedDeviceKey.createRandomKey();
+        await dehydratedDevices.saveDehydratedDeviceKey(key);
+        expect((await dehydratedDevices.getDehydratedDeviceKey()).toBase64()).toStrictEqual(key.toBase64());
+
+        await dehydratedDevices.deleteDehydratedDeviceKey();
+        expect(await dehydratedDevices.getDehydratedDeviceKey()).toBeNull();
+    });
+});
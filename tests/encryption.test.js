@@ -1,4 +1,11 @@
-const { EncryptionAlgorithm, EncryptionSettings, HistoryVisibility, VerificationState } = require("../");
+const {
+    DecryptionSettings,
+    EncryptionAlgorithm,
+    EncryptionSettings,
+    HistoryVisibility,
+    TrustRequirement,
+    VerificationState,
+} = require("../");
 
 describe("EncryptionAlgorithm", () => {
     test("has the correct variant values", () => {
@@ -28,3 +35,23 @@ describe(EncryptionSettings.name, () => {
         }).toThrow();
     });
 });
+
+describe("TrustRequirement", () => {
+    test("has the correct variant values", () => {
+        expect(TrustRequirement.Untrusted).toStrictEqual(0);
+        expect(TrustRequirement.CrossSignedOrLegacy).toStrictEqual(1);
+        expect(TrustRequirement.CrossSigned).toStrictEqual(2);
+    });
+});
+
+describe(DecryptionSettings.name, () => {
+    test("can be instantiated with a trust requirement", () => {
+        const ds = new DecryptionSettings(TrustRequirement.CrossSigned);
+
+        expect(ds.senderDeviceTrustRequirement).toStrictEqual(TrustRequirement.CrossSigned);
+
+        ds.senderDeviceTrustRequirement = TrustRequirement.Untrusted;
+
+        expect(ds.senderDeviceTrustRequirement).toStrictEqual(TrustRequirement.Untrusted);
+    });
+});
@@ -0,0 +1,140 @@
+const { OlmMachine, UserId, DeviceId, DeviceLists, RequestType, StoreType, ToDeviceRequest } = require("../");
+
+// Deliver a to-device request from one machine to another one, as if it
+// went through the homeserver.
+//
+// Returns the result of `receiveSyncChanges`.
+async function deliver(from, to, request) {
+    expect(request).toBeInstanceOf(ToDeviceRequest);
+
+    const messages = JSON.parse(request.body).messages;
+    const toDeviceEvents = [];
+
+    for (const devices of Object.values(messages)) {
+        for (const content of Object.values(devices)) {
+            toDeviceEvents.push({
+                sender: from.userId.toString(),
+                type: request.eventType,
+                content,
+            });
+        }
+    }
+
+    return await to.receiveSyncChanges(JSON.stringify(toDeviceEvents), new DeviceLists(), {}, []);
+}
+
+// Deliver all the pending outgoing verification to-device requests of a
+// machine to another one.
+async function deliverOutgoing(from, to) {
+    for (const request of await from.outgoingRequests()) {
+        if (request.type !== RequestType.ToDevice || !request.eventType.startsWith("m.key.verification")) {
+            continue;
+        }
+
+        await deliver(from, to, request);
+        await from.markRequestAsSent(request.id, request.type, "{}");
+    }
+}
+
+// Create two machines that know about each other's devices.
+//
+// Returns both machines, and the device keys they uploaded, by user ID and
+// device ID.
+//
+// If `withOlmSession` is set, Alice also claims a one-time key of Bob, so
+// that she has an Olm session with him. If `bobStore` is set, it is the
+// `CustomStore` Bob's machine is backed by.
+async function machinesThatKnowEachOther({ withOlmSession = false, bobStore = null } = {}) {
+    const alice = await OlmMachine.initialize(new UserId("@alice:example.org"), new DeviceId("ALICEDEVICE"));
+    const bob = bobStore
+        ? await OlmMachine.initialize(
+              new UserId("@bob:example.org"),
+              new DeviceId("BOBDEVICE"),
+              null,
+              null,
+              StoreType.Custom,
+              bobStore,
+          )
+        : await OlmMachine.initialize(new UserId("@bob:example.org"), new DeviceId("BOBDEVICE"));
+
+    await alice.updateTrackedUsers([bob.userId]);
+    await bob.updateTrackedUsers([alice.userId]);
+
+    const deviceKeys = {};
+    const oneTimeKeys = {};
+    const keysQueries = [];
+
+    for (const m of [alice, bob]) {
+        for (const request of await m.outgoingRequests()) {
+            if (request.type === RequestType.KeysUpload) {
+                const body = JSON.parse(request.body);
+
+                deviceKeys[m.userId.toString()] = {
+                    [m.deviceId.toString()]: body.device_keys,
+                };
+                oneTimeKeys[m.userId.toString()] = body.one_time_keys;
+
+                await m.markRequestAsSent(
+                    request.id,
+                    request.type,
+                    JSON.stringify({ one_time_key_counts: { signed_curve25519: 50 } }),
+                );
+            } else if (request.type === RequestType.KeysQuery) {
+                keysQueries.push([m, request]);
+            }
+        }
+    }
+
+    for (const [m, request] of keysQueries) {
+        await m.markRequestAsSent(
+            request.id,
+            request.type,
+            JSON.stringify({ device_keys: deviceKeys, failures: {} }),
+        );
+    }
+
+    if (withOlmSession) {
+        const request = await alice.getMissingSessions([bob.userId]);
+        const [keyId, key] = Object.entries(oneTimeKeys[bob.userId.toString()])[0];
+
+        await alice.markRequestAsSent(
+            request.id,
+            request.type,
+            JSON.stringify({
+                one_time_keys: {
+                    [bob.userId.toString()]: { [bob.deviceId.toString()]: { [keyId]: key } },
+                },
+                failures: {},
+            }),
+        );
+    }
+
+    return [alice, bob, deviceKeys];
+}
+
+// Send out the initial keys upload and keys query requests of a machine, as
+// if the homeserver knew about the given cross-signing keys of its owner, as
+// returned in the `uploadSigningKeysReq` of `bootstrapCrossSigning`.
+async function learnOwnCrossSigningKeys(m, crossSigningKeys) {
+    const [keysUpload, keysQuery] = await m.outgoingRequests();
+    const userId = m.userId.toString();
+
+    await m.markRequestAsSent(
+        keysUpload.id,
+        keysUpload.type,
+        JSON.stringify({ one_time_key_counts: { signed_curve25519: 100 } }),
+    );
+    await m.markRequestAsSent(
+        keysQuery.id,
+        keysQuery.type,
+        JSON.stringify({
+            device_keys: { [userId]: { [m.deviceId.toString()]: JSON.parse(keysUpload.body).device_keys } },
+            failures: {},
+            master_keys: { [userId]: crossSigningKeys.master_key },
+            self_signing_keys: { [userId]: crossSigningKeys.self_signing_key },
+            user_signing_keys: { [userId]: crossSigningKeys.user_signing_key },
+        }),
+    );
+}
+
+module.exports = { deliver, deliverOutgoing, learnOwnCrossSigningKeys, machinesThatKnowEachOther };
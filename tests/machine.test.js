@@ -20,14 +20,45 @@ const {
     SignatureState,
     BackupDecryptionKey,
     SecretStorageKey,
+    SecretStorageItems,
+    SecretStorageBootstrapResult,
+    AccountDataEvent,
+    CrossSigningBootstrapRequests,
+    OwnUserIdentity,
+    OtherUserIdentity,
+    UserDevices,
+    Device,
+    LocalTrust,
+    EncryptionAlgorithm,
+    RoomKeyImportResult,
+    DecryptionSettings,
+    TrustRequirement,
+    DecryptionErrorCode,
+    SyncChangesResult,
+    ProcessedToDeviceEvent,
+    ProcessedToDeviceEventType,
+    RoomKeyInfo,
+    RoomKeyWithheldInfo,
+    RoomKeyShareResult,
+    RoomSettings,
+    HistoryVisibility,
+    OutboundGroupSessionInfo,
+    SharedWithDevice,
+    KeysClaimRequest,
+    ToDeviceEncryptionResult,
+    ToDeviceEncryptionFailure,
+    SignatureUploadRequest,
 } = require("../");
 const path = require("path");
 const os = require("os");
 const fs = require("fs/promises");
+const { deliver, learnOwnCrossSigningKeys, machinesThatKnowEachOther } = require("./helpers");
 
 describe("StoreType", () => {
     test("has the correct variant values", () => {
         expect(StoreType.Sqlite).toStrictEqual(0);
+        expect(StoreType.Custom).toStrictEqual(1);
+        expect(StoreType.Memory).toStrictEqual(2);
     });
 });
 
@@ -137,11 +168,16 @@ describe(OlmMachine.name, () => {
         const oneTimeKeyCounts = {};
         const unusedFallbackKeys = [];
 
-        const receiveSyncChanges = JSON.parse(
-            await m.receiveSyncChanges(toDeviceEvents, changedDevices, oneTimeKeyCounts, unusedFallbackKeys),
+        const receiveSyncChanges = await m.receiveSyncChanges(
+            toDeviceEvents,
+            changedDevices,
+            oneTimeKeyCounts,
+            unusedFallbackKeys,
         );
 
-        expect(receiveSyncChanges).toEqual([[], []]);
+        expect(receiveSyncChanges).toBeInstanceOf(SyncChangesResult);
+        expect(receiveSyncChanges.toDeviceEvents).toEqual([]);
+        expect(receiveSyncChanges.roomKeyInfos).toEqual([]);
     });
 
     test("can get the outgoing requests that need to be sent out", async () => {
@@ -151,11 +187,16 @@ describe(OlmMachine.name, () => {
         const oneTimeKeyCounts = {};
         const unusedFallbackKeys = [];
 
-        const receiveSyncChanges = JSON.parse(
-            await m.receiveSyncChanges(toDeviceEvents, changedDevices, oneTimeKeyCounts, unusedFallbackKeys),
+        const receiveSyncChanges = await m.receiveSyncChanges(
+            toDeviceEvents,
+            changedDevices,
+            oneTimeKeyCounts,
+            unusedFallbackKeys,
         );
 
-        expect(receiveSyncChanges).toEqual([[], []]);
+        expect(receiveSyncChanges).toBeInstanceOf(SyncChangesResult);
+        expect(receiveSyncChanges.toDeviceEvents).toEqual([]);
+        expect(receiveSyncChanges.roomKeyInfos).toEqual([]);
 
         const outgoingRequests = await m.outgoingRequests();
 
@@ -364,11 +405,78 @@ describe(OlmMachine.name, () => {
             }
         });
 
+        test("can get the devices of another user", async () => {
+            const devices = await m.getUserDevices(new UserId("@example:localhost"));
+
+            expect(devices).toBeInstanceOf(UserDevices);
+            expect(devices.keys().map((deviceId) => deviceId.toString())).toEqual(["AFGUOBTZWM"]);
+            expect(devices.devices()).toHaveLength(1);
+            expect(devices.devices()[0]).toBeInstanceOf(Device);
+            expect(devices.get(new DeviceId("AFGUOBTZWM"))).toBeInstanceOf(Device);
+            expect(devices.get(new DeviceId("UNKNOWN"))).toBeNull();
+            expect(devices.isAnyVerified()).toStrictEqual(false);
+
+            const iterated = [];
+            for (const device of devices) {
+                iterated.push(device.deviceId.toString());
+            }
+            expect(iterated).toEqual(["AFGUOBTZWM"]);
+            expect([...devices].map((device) => device.deviceId.toString())).toEqual(["AFGUOBTZWM"]);
+
+            const noDevices = await m.getUserDevices(new UserId("@unknown:localhost"));
+
+            expect(noDevices.keys()).toHaveLength(0);
+            expect([...noDevices]).toHaveLength(0);
+        });
+
+        test("can read and update the trust of a device", async () => {
+            const device = await m.getDevice(new UserId("@example:localhost"), new DeviceId("AFGUOBTZWM"));
+
+            expect(device).toBeInstanceOf(Device);
+            expect(device.userId.toString()).toStrictEqual("@example:localhost");
+            expect(device.deviceId.toString()).toStrictEqual("AFGUOBTZWM");
+            expect(device.displayName).toStrictEqual("rust-sdk");
+            expect(device.curve25519Key.toBase64()).toStrictEqual("boYjDpaC+7NkECQEeMh5dC+I1+AfriX0VXG2UV7EUQo");
+            expect(device.ed25519Key.toBase64()).toStrictEqual("NayrMQ33ObqMRqz6R9GosmHdT6HQ6b/RX/3QlZ2yiec");
+            expect(device.algorithms).toEqual([
+                EncryptionAlgorithm.OlmV1Curve25519AesSha2,
+                EncryptionAlgorithm.MegolmV1AesSha2,
+            ]);
+            expect(device.firstTimeSeen).toBeGreaterThan(0);
+            expect(device.isDeleted()).toStrictEqual(false);
+            expect(device.isBlacklisted()).toStrictEqual(false);
+            expect(device.localTrustState).toStrictEqual(LocalTrust.Unset);
+
+            await device.setLocalTrust(LocalTrust.BlackListed);
+
+            const updatedDevice = await m.getDevice(new UserId("@example:localhost"), new DeviceId("AFGUOBTZWM"));
+
+            expect(updatedDevice.isBlacklisted()).toStrictEqual(true);
+            expect(updatedDevice.localTrustState).toStrictEqual(LocalTrust.BlackListed);
+
+            await updatedDevice.setLocalTrust(LocalTrust.Unset);
+        });
+
+        test("can get the identity of another user", async () => {
+            const identity = await m.getIdentity(new UserId("@example:localhost"));
+
+            expect(identity).toBeInstanceOf(OtherUserIdentity);
+            expect(identity.userId.toString()).toStrictEqual("@example:localhost");
+            expect(identity.isVerified()).toStrictEqual(false);
+            expect(identity.hasVerificationViolation()).toStrictEqual(false);
+            expect(identity.identityNeedsUserApproval()).toStrictEqual(false);
+            expect(JSON.parse(identity.masterKey).usage).toEqual(["master"]);
+
+            expect(await m.getIdentity(new UserId("@unknown:localhost"))).toBeNull();
+        });
+
         test("can share a room key", async () => {
             const other_users = [new UserId("@example:localhost")];
 
-            const requests = await m.shareRoomKey(room, other_users, new EncryptionSettings());
+            const result = await m.shareRoomKey(room, other_users, new EncryptionSettings());
+            const requests = result.requests;
 
+            expect(result).toBeInstanceOf(RoomKeyShareResult);
             expect(requests).toHaveLength(1);
             expect(requests[0]).toBeInstanceOf(ToDeviceRequest);
             expect(requests[0].eventType).toBeDefined();
@@ -425,6 +533,45 @@ describe(OlmMachine.name, () => {
             expect(decrypted.shieldState(true).color).toStrictEqual(ShieldColor.Red);
             expect(decrypted.shieldState(false).color).toStrictEqual(ShieldColor.Red);
         });
+
+        test("can decrypt an event with decryption settings", async () => {
+            const decrypted = await m.decryptRoomEvent(
+                JSON.stringify({
+                    type: "m.room.encrypted",
+                    event_id: "$xxxxx:example.org",
+                    origin_server_ts: Date.now(),
+                    sender: user.toString(),
+                    content: encrypted,
+                }),
+                room,
+                new DecryptionSettings(TrustRequirement.Untrusted),
+            );
+
+            expect(JSON.parse(decrypted.event).content.hello).toStrictEqual("world");
+        });
+
+        test("raises a structured error when the room key is missing", async () => {
+            const other = await machine(new UserId("@bob:example.org"), new DeviceId("BOBDEVICE"));
+
+            const error = await other
+                .decryptRoomEvent(
+                    JSON.stringify({
+                        type: "m.room.encrypted",
+                        event_id: "$xxxxx:example.org",
+                        origin_server_ts: Date.now(),
+                        sender: user.toString(),
+                        content: encrypted,
+                    }),
+                    room,
+                )
+                .catch((error) => error);
+
+            expect(error).toBeInstanceOf(Error);
+            expect(error.name).toStrictEqual("MegolmDecryptionError");
+            expect(error.code).toStrictEqual(DecryptionErrorCode.MissingRoomKey);
+            expect(error.sessionId).toStrictEqual(encrypted.session_id);
+            expect(error.withheldCode).toBeNull();
+        });
     });
 
     test("can update tracked users", async () => {
@@ -486,6 +633,24 @@ describe(OlmMachine.name, () => {
         expect(crossSigningStatus.hasUserSigning).toStrictEqual(true);
     });
 
+    test("can get our own identity", async () => {
+        const m = await machine();
+
+        expect(await m.getIdentity(user)).toBeNull();
+
+        await m.bootstrapCrossSigning(true);
+
+        const identity = await m.getIdentity(user);
+
+        expect(identity).toBeInstanceOf(OwnUserIdentity);
+        expect(identity.userId.toString()).toStrictEqual(user.toString());
+        expect(identity.isVerified()).toStrictEqual(true);
+        expect(identity.hasVerificationViolation()).toStrictEqual(false);
+        expect(JSON.parse(identity.masterKey).user_id).toStrictEqual(user.toString());
+        expect(JSON.parse(identity.selfSigningKey).usage).toEqual(["self_signing"]);
+        expect(JSON.parse(identity.userSigningKey).usage).toEqual(["user_signing"]);
+    });
+
     test("can sign a message", async () => {
         const m = await machine();
         const signatures = await m.sign("foo");
@@ -628,6 +793,70 @@ describe(OlmMachine.name, () => {
             expect(newCounts.backedUp).toStrictEqual(1);
         });
 
+        test("can import backed up room keys", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const keyBackupKey = BackupDecryptionKey.createRandomKey();
+            await m.enableBackupV1(keyBackupKey.megolmV1PublicKey.publicKeyBase64, "1");
+
+            const outgoing = await m.backupRoomKeys();
+            const sessions = JSON.parse(outgoing.body).rooms[room.toString()].sessions;
+
+            // Restore the backup on a new machine.
+            const m2 = await machine(new UserId("@alice:example.org"), new DeviceId("OTHERDEVICE"));
+
+            const backedUpRoomKeys = JSON.stringify({ [room.toString()]: sessions });
+
+            await expect(m2.importBackedUpRoomKeys(backedUpRoomKeys, "1")).rejects.toThrow(
+                "No backup decryption key has been saved",
+            );
+
+            await m2.saveBackupDecryptionKey(keyBackupKey, "1");
+            await m2.enableBackupV1(keyBackupKey.megolmV1PublicKey.publicKeyBase64, "1");
+
+            const result = await m2.importBackedUpRoomKeys(backedUpRoomKeys, "1");
+
+            expect(result).toBeInstanceOf(RoomKeyImportResult);
+            expect(result.importedCount).toStrictEqual(1);
+            expect(result.totalCount).toStrictEqual(1);
+            expect(result.failedCount).toStrictEqual(0);
+
+            const counts = await m2.roomKeyCounts();
+
+            expect(counts.total).toStrictEqual(1);
+            expect(counts.backedUp).toStrictEqual(1);
+        });
+
+        test("counts the backed up room keys that cannot be decrypted", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const keyBackupKey = BackupDecryptionKey.createRandomKey();
+            await m.enableBackupV1(keyBackupKey.megolmV1PublicKey.publicKeyBase64, "1");
+
+            const outgoing = await m.backupRoomKeys();
+            const sessions = JSON.parse(outgoing.body).rooms[room.toString()].sessions;
+            const [backedUpSession] = Object.values(sessions);
+
+            const garbage = {
+                ...backedUpSession,
+                session_data: { ephemeral: "AAAA", ciphertext: "AAAA", mac: "AAAA" },
+            };
+            const backedUpRoomKeys = JSON.stringify({
+                [room.toString()]: { ...sessions, garbageSessionId: garbage },
+            });
+
+            const m2 = await machine(new UserId("@alice:example.org"), new DeviceId("OTHERDEVICE"));
+            await m2.saveBackupDecryptionKey(keyBackupKey, "1");
+
+            const result = await m2.importBackedUpRoomKeys(backedUpRoomKeys, "1");
+
+            expect(result.importedCount).toStrictEqual(1);
+            expect(result.totalCount).toStrictEqual(1);
+            expect(result.failedCount).toStrictEqual(1);
+        });
+
         test("can save and get private key", async () => {
             let m = await machine();
 
@@ -642,6 +871,752 @@ describe(OlmMachine.name, () => {
         });
     });
 
+    describe("room key export", () => {
+        test("can export and import room keys", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const exported = await m.exportRoomKeys(null, "passphrase", 10000);
+            expect(exported).toMatch(/^-----BEGIN MEGOLM SESSION DATA-----/);
+
+            const m2 = await machine(new UserId("@alice:example.org"), new DeviceId("OTHERDEVICE"));
+            let progressed;
+            const progressListenerCalled = new Promise((resolve) => (progressed = resolve));
+            const progressListener = jest.fn(() => progressed());
+            const result = await m2.importRoomKeys(exported, "passphrase", progressListener);
+
+            expect(result).toBeInstanceOf(RoomKeyImportResult);
+            expect(result.importedCount).toStrictEqual(1);
+            expect(result.totalCount).toStrictEqual(1);
+            expect(result.roomIds().map((roomId) => roomId.toString())).toEqual([room.toString()]);
+            expect(Object.keys(result.keys())).toEqual([room.toString()]);
+
+            await progressListenerCalled;
+            expect(progressListener).toHaveBeenCalledWith(expect.any(Number), 1);
+
+            expect((await m2.roomKeyCounts()).total).toStrictEqual(1);
+        });
+
+        test("notifies about the imported room keys", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const exported = await m.exportRoomKeys(null, "passphrase", 10000);
+
+            const m2 = await machine(new UserId("@alice:example.org"), new DeviceId("OTHERDEVICE"));
+            const roomKeyInfos = new Promise((resolve) => m2.registerRoomKeyUpdatedCallback(resolve));
+
+            await m2.importRoomKeys(exported, "passphrase");
+
+            const [roomKeyInfo] = await roomKeyInfos;
+            expect(roomKeyInfo).toBeInstanceOf(RoomKeyInfo);
+            expect(roomKeyInfo.roomId.toString()).toStrictEqual(room.toString());
+        });
+
+        test("can filter the exported room keys by room", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const exported = await m.exportRoomKeys([new RoomId("!other:matrix.org")], "passphrase", 10000);
+
+            const m2 = await machine(new UserId("@alice:example.org"), new DeviceId("OTHERDEVICE"));
+            const result = await m2.importRoomKeys(exported, "passphrase");
+
+            expect(result.totalCount).toStrictEqual(0);
+        });
+
+        test("cannot import room keys with the wrong passphrase", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const exported = await m.exportRoomKeys(null, "passphrase", 10000);
+
+            await expect(m.importRoomKeys(exported, "wrong passphrase")).rejects.toThrow();
+        });
+    });
+
+    describe("to-device encryption", () => {
+        test("can encrypt a to-device event for a device", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+
+            const result = await bobDevice.encryptToDeviceEvent("org.example.ping", JSON.stringify({ foo: "bar" }));
+
+            expect(result).toBeInstanceOf(ToDeviceEncryptionResult);
+            expect(result.keysClaimRequest).toBeNull();
+            expect(result.failedDevices).toEqual([]);
+
+            const request = result.toDeviceRequest;
+            expect(request).toBeInstanceOf(ToDeviceRequest);
+            expect(request.eventType).toStrictEqual("m.room.encrypted");
+
+            const { toDeviceEvents } = await deliver(alice, bob, request);
+
+            expect(toDeviceEvents).toHaveLength(1);
+            expect(toDeviceEvents[0]).toBeInstanceOf(ProcessedToDeviceEvent);
+            expect(toDeviceEvents[0].type).toStrictEqual(ProcessedToDeviceEventType.Decrypted);
+            expect(JSON.parse(toDeviceEvents[0].rawEvent)).toMatchObject({
+                sender: alice.userId.toString(),
+                type: "org.example.ping",
+                content: { foo: "bar" },
+            });
+        });
+
+        test("can encrypt a to-device event for several devices", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+
+            const empty = await alice.encryptToDeviceEvent("org.example.ping", "{}", []);
+
+            expect(empty).toBeInstanceOf(ToDeviceEncryptionResult);
+            expect(empty.keysClaimRequest).toBeNull();
+            expect(empty.toDeviceRequest).toBeNull();
+            expect(empty.failedDevices).toEqual([]);
+
+            const result = await alice.encryptToDeviceEvent("org.example.ping", "{}", [bobDevice]);
+
+            expect(result.keysClaimRequest).toBeNull();
+            expect(result.failedDevices).toEqual([]);
+            expect(result.toDeviceRequest).toBeInstanceOf(ToDeviceRequest);
+            expect(JSON.parse(result.toDeviceRequest.body).messages).toHaveProperty([
+                bob.userId.toString(),
+                bob.deviceId.toString(),
+            ]);
+        });
+
+        test("claims the missing Olm sessions before encrypting a to-device event", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+
+            const result = await alice.encryptToDeviceEvent("org.example.ping", "{}", [bobDevice]);
+
+            expect(result.toDeviceRequest).toBeNull();
+            expect(result.keysClaimRequest).toBeInstanceOf(KeysClaimRequest);
+            expect(JSON.parse(result.keysClaimRequest.body).one_time_keys).toHaveProperty([
+                bob.userId.toString(),
+                bob.deviceId.toString(),
+            ]);
+
+            // The homeserver has no one-time key left for Bob's device.
+            await alice.markRequestAsSent(
+                result.keysClaimRequest.id,
+                result.keysClaimRequest.type,
+                JSON.stringify({ one_time_keys: {}, failures: {} }),
+            );
+
+            const retried = await alice.encryptToDeviceEvent("org.example.ping", "{}", [bobDevice]);
+
+            expect(retried.keysClaimRequest).toBeNull();
+            expect(retried.toDeviceRequest).toBeNull();
+            expect(retried.failedDevices).toHaveLength(1);
+            expect(retried.failedDevices[0]).toBeInstanceOf(ToDeviceEncryptionFailure);
+            expect(retried.failedDevices[0].userId.toString()).toStrictEqual(bob.userId.toString());
+            expect(retried.failedDevices[0].deviceId.toString()).toStrictEqual(bob.deviceId.toString());
+            expect(retried.failedDevices[0].reason).toBeTruthy();
+        });
+
+        test("only claims the Olm sessions missing with the given devices", async () => {
+            const [alice, bob, deviceKeys] = await machinesThatKnowEachOther();
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+
+            // Bob logs in on another device.
+            const bobOtherDevice = await OlmMachine.initialize(bob.userId, new DeviceId("BOBOTHERDEVICE"));
+            const [keysUpload] = await bobOtherDevice.outgoingRequests();
+
+            await alice.receiveSyncChanges("[]", new DeviceLists([bob.userId], []), {}, []);
+            const keysQuery = (await alice.outgoingRequests()).find(
+                (request) => request.type === RequestType.KeysQuery,
+            );
+            await alice.markRequestAsSent(
+                keysQuery.id,
+                keysQuery.type,
+                JSON.stringify({
+                    device_keys: {
+                        [bob.userId.toString()]: {
+                            ...deviceKeys[bob.userId.toString()],
+                            BOBOTHERDEVICE: JSON.parse(keysUpload.body).device_keys,
+                        },
+                    },
+                    failures: {},
+                }),
+            );
+            expect(await alice.getDevice(bob.userId, new DeviceId("BOBOTHERDEVICE"))).toBeInstanceOf(Device);
+
+            const result = await alice.encryptToDeviceEvent("org.example.ping", "{}", [bobDevice]);
+
+            expect(result.keysClaimRequest).toBeInstanceOf(KeysClaimRequest);
+            expect(JSON.parse(result.keysClaimRequest.body).one_time_keys).toStrictEqual({
+                [bob.userId.toString()]: { [bob.deviceId.toString()]: "signed_curve25519" },
+            });
+        });
+
+        test("can receive information about the room keys", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+            const {
+                requests: [request],
+            } = await alice.shareRoomKey(room, [bob.userId], new EncryptionSettings());
+
+            const { toDeviceEvents, roomKeyInfos } = await deliver(alice, bob, request);
+
+            expect(toDeviceEvents).toHaveLength(1);
+            expect(roomKeyInfos).toHaveLength(1);
+            expect(roomKeyInfos[0]).toBeInstanceOf(RoomKeyInfo);
+            expect(roomKeyInfos[0].algorithm).toStrictEqual(EncryptionAlgorithm.MegolmV1AesSha2);
+            expect(roomKeyInfos[0].roomId.toString()).toStrictEqual(room.toString());
+            expect(roomKeyInfos[0].senderKey.toBase64()).toStrictEqual(alice.identityKeys.curve25519.toBase64());
+            expect(roomKeyInfos[0].sessionId).toBeDefined();
+        });
+
+        test("only decrypts events from cross-signed senders with `TrustRequirement.CrossSigned`", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+            const { requests } = await alice.shareRoomKey(room, [bob.userId], new EncryptionSettings());
+            for (const request of requests) {
+                await deliver(alice, bob, request);
+            }
+
+            const encrypted = await alice.encryptRoomEvent(room, "m.room.message", JSON.stringify({ hello: "world" }));
+            const event = JSON.stringify({
+                type: "m.room.encrypted",
+                event_id: "$xxxxx:example.org",
+                origin_server_ts: Date.now(),
+                sender: alice.userId.toString(),
+                content: JSON.parse(encrypted),
+            });
+
+            // Alice's device is known, but it isn't cross-signed.
+            const error = await bob
+                .decryptRoomEvent(event, room, new DecryptionSettings(TrustRequirement.CrossSigned))
+                .catch((error) => error);
+
+            expect(error).toBeInstanceOf(Error);
+            expect(error.name).toStrictEqual("MegolmDecryptionError");
+            expect(error.code).toStrictEqual(DecryptionErrorCode.SenderIdentityNotTrusted);
+
+            const decrypted = await bob.decryptRoomEvent(
+                event,
+                room,
+                new DecryptionSettings(TrustRequirement.Untrusted),
+            );
+
+            expect(JSON.parse(decrypted.event).content.hello).toStrictEqual("world");
+        });
+
+        test("notifies about the withheld room keys", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+            await bobDevice.setLocalTrust(LocalTrust.BlackListed);
+
+            const withheldInfos = new Promise((resolve) => bob.registerRoomKeysWithheldCallback(resolve));
+
+            const { requests } = await alice.shareRoomKey(room, [bob.userId], new EncryptionSettings());
+            for (const request of requests) {
+                await deliver(alice, bob, request);
+            }
+
+            const [withheldInfo] = await withheldInfos;
+            expect(withheldInfo).toBeInstanceOf(RoomKeyWithheldInfo);
+            expect(withheldInfo.sender.toString()).toStrictEqual(alice.userId.toString());
+            expect(withheldInfo.roomId.toString()).toStrictEqual(room.toString());
+            expect(withheldInfo.withheldCode).toStrictEqual("m.blacklisted");
+        });
+
+        test("sends the withheld notifications with the room key", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+            await bobDevice.setLocalTrust(LocalTrust.BlackListed);
+
+            const result = await alice.shareRoomKey(room, [bob.userId], new EncryptionSettings());
+
+            const withheldRequests = result.requests.filter((request) => request.eventType === "m.room_key.withheld");
+            expect(withheldRequests).toHaveLength(1);
+            expect(JSON.parse(withheldRequests[0].body).messages).toHaveProperty([
+                bob.userId.toString(),
+                bob.deviceId.toString(),
+            ]);
+        });
+
+        test("claims the missing Olm session before encrypting a to-device event for a device", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+            const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+
+            const result = await bobDevice.encryptToDeviceEvent("org.example.ping", "{}");
+
+            expect(result.toDeviceRequest).toBeNull();
+            expect(result.keysClaimRequest).toBeInstanceOf(KeysClaimRequest);
+            expect(JSON.parse(result.keysClaimRequest.body).one_time_keys).toStrictEqual({
+                [bob.userId.toString()]: { [bob.deviceId.toString()]: "signed_curve25519" },
+            });
+        });
+    });
+
+    describe("identity and device updates", () => {
+        test("notifies about new devices", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+
+            const otherBob = await OlmMachine.initialize(bob.userId, new DeviceId("OTHERDEVICE"));
+            const [keysUpload] = await otherBob.outgoingRequests();
+            const deviceKeys = JSON.parse(keysUpload.body).device_keys;
+
+            const userIds = new Promise((resolve) => alice.registerDevicesUpdatedCallback(resolve));
+
+            const request = alice.queryKeysForUsers([bob.userId]);
+            await alice.markRequestAsSent(
+                request.id,
+                request.type,
+                JSON.stringify({
+                    device_keys: { [bob.userId.toString()]: { OTHERDEVICE: deviceKeys } },
+                    failures: {},
+                }),
+            );
+
+            const [userId] = await userIds;
+            expect(userId).toBeInstanceOf(UserId);
+            expect(userId.toString()).toStrictEqual(bob.userId.toString());
+        });
+
+        test("notifies about new user identities", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+
+            const { uploadSigningKeysReq } = await bob.bootstrapCrossSigning(true);
+            const signingKeys = JSON.parse(uploadSigningKeysReq);
+
+            const userIds = new Promise((resolve) => alice.registerUserIdentityUpdatedCallback(resolve));
+
+            const request = alice.queryKeysForUsers([bob.userId]);
+            await alice.markRequestAsSent(
+                request.id,
+                request.type,
+                JSON.stringify({
+                    device_keys: {},
+                    master_keys: { [bob.userId.toString()]: signingKeys.master_key },
+                    self_signing_keys: { [bob.userId.toString()]: signingKeys.self_signing_key },
+                    failures: {},
+                }),
+            );
+
+            const [userId] = await userIds;
+            expect(userId).toBeInstanceOf(UserId);
+            expect(userId.toString()).toStrictEqual(bob.userId.toString());
+        });
+
+        test("only calls the last registered callback", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+
+            const otherBob = await OlmMachine.initialize(bob.userId, new DeviceId("OTHERDEVICE"));
+            const [keysUpload] = await otherBob.outgoingRequests();
+            const deviceKeys = JSON.parse(keysUpload.body).device_keys;
+
+            const replacedCallback = jest.fn();
+            alice.registerDevicesUpdatedCallback(replacedCallback);
+            const userIds = new Promise((resolve) => alice.registerDevicesUpdatedCallback(resolve));
+
+            const request = alice.queryKeysForUsers([bob.userId]);
+            await alice.markRequestAsSent(
+                request.id,
+                request.type,
+                JSON.stringify({
+                    device_keys: { [bob.userId.toString()]: { OTHERDEVICE: deviceKeys } },
+                    failures: {},
+                }),
+            );
+
+            expect(await userIds).toHaveLength(1);
+            expect(replacedCallback).not.toHaveBeenCalled();
+        });
+
+        test("stops calling the callbacks once closed", async () => {
+            const m = await machine();
+
+            const callback = jest.fn();
+            m.registerRoomKeyUpdatedCallback(callback);
+            m.registerRoomKeysWithheldCallback(callback);
+            m.registerUserIdentityUpdatedCallback(callback);
+            m.registerDevicesUpdatedCallback(callback);
+
+            m.close();
+            await new Promise((resolve) => setImmediate(resolve));
+
+            expect(callback).not.toHaveBeenCalled();
+        });
+    });
+
+    describe("user identities", () => {
+        // Answer a keys query of `m` for a user, as if the homeserver knew about
+        // the given cross-signing keys of theirs, as returned in the
+        // `uploadSigningKeysReq` of `bootstrapCrossSigning`.
+        async function receiveCrossSigningKeys(m, userId, crossSigningKeys) {
+            const request = m.queryKeysForUsers([userId]);
+
+            await m.markRequestAsSent(
+                request.id,
+                request.type,
+                JSON.stringify({
+                    device_keys: {},
+                    master_keys: { [userId.toString()]: crossSigningKeys.master_key },
+                    self_signing_keys: { [userId.toString()]: crossSigningKeys.self_signing_key },
+                    user_signing_keys: { [userId.toString()]: crossSigningKeys.user_signing_key },
+                    failures: {},
+                }),
+            );
+        }
+
+        // Create two devices of the same user, the second one only knowing
+        // about the public cross-signing keys created by the first one.
+        async function ownDevices() {
+            const m = await machine();
+            const crossSigningKeys = JSON.parse((await m.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+
+            const otherDevice = await machine(user, new DeviceId("OTHERDEVICE"));
+            await learnOwnCrossSigningKeys(otherDevice, crossSigningKeys);
+
+            return [m, otherDevice];
+        }
+
+        test("can verify our own identity", async () => {
+            const [, otherDevice] = await ownDevices();
+
+            const identity = await otherDevice.getIdentity(user);
+            expect(identity).toBeInstanceOf(OwnUserIdentity);
+            expect(identity.isVerified()).toStrictEqual(false);
+
+            const request = await identity.verify();
+
+            expect(request).toBeInstanceOf(SignatureUploadRequest);
+            const [signedMasterKey] = Object.values(JSON.parse(request.body).signed_keys[user.toString()]);
+            expect(signedMasterKey.usage).toEqual(["master"]);
+            expect(signedMasterKey.signatures[user.toString()]).toHaveProperty("ed25519:OTHERDEVICE");
+
+            const verifiedIdentity = await otherDevice.getIdentity(user);
+            expect(verifiedIdentity.isVerified()).toStrictEqual(true);
+            expect(verifiedIdentity.wasPreviouslyVerified()).toStrictEqual(true);
+        });
+
+        test("can withdraw the verification of our own identity", async () => {
+            const [m, otherDevice] = await ownDevices();
+            await (await otherDevice.getIdentity(user)).verify();
+
+            // The cross-signing keys are reset by the first device.
+            const newCrossSigningKeys = JSON.parse((await m.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+            await receiveCrossSigningKeys(otherDevice, user, newCrossSigningKeys);
+
+            const identity = await otherDevice.getIdentity(user);
+            expect(identity.isVerified()).toStrictEqual(false);
+            expect(identity.hasVerificationViolation()).toStrictEqual(true);
+
+            await identity.withdrawVerification();
+
+            const withdrawnIdentity = await otherDevice.getIdentity(user);
+            expect(withdrawnIdentity.wasPreviouslyVerified()).toStrictEqual(false);
+            expect(withdrawnIdentity.hasVerificationViolation()).toStrictEqual(false);
+        });
+
+        test("can verify the identity of another user", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+            await alice.bootstrapCrossSigning(true);
+            const bobCrossSigningKeys = JSON.parse((await bob.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+            await receiveCrossSigningKeys(alice, bob.userId, bobCrossSigningKeys);
+
+            const identity = await alice.getIdentity(bob.userId);
+            expect(identity).toBeInstanceOf(OtherUserIdentity);
+            expect(identity.isVerified()).toStrictEqual(false);
+
+            const request = await identity.verify();
+
+            expect(request).toBeInstanceOf(SignatureUploadRequest);
+            const [signedMasterKey] = Object.values(JSON.parse(request.body).signed_keys[bob.userId.toString()]);
+            expect(signedMasterKey.usage).toEqual(["master"]);
+            expect(signedMasterKey.signatures).toHaveProperty(alice.userId.toString());
+
+            // The homeserver returns Bob's master key with Alice's signature
+            // once it has been uploaded.
+            const masterKey = bobCrossSigningKeys.master_key;
+            await receiveCrossSigningKeys(alice, bob.userId, {
+                ...bobCrossSigningKeys,
+                master_key: { ...masterKey, signatures: { ...masterKey.signatures, ...signedMasterKey.signatures } },
+            });
+
+            expect((await alice.getIdentity(bob.userId)).isVerified()).toStrictEqual(true);
+        });
+
+        test("can pin the current master key of another user", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+
+            const crossSigningKeys = JSON.parse((await bob.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+            await receiveCrossSigningKeys(alice, bob.userId, crossSigningKeys);
+            expect((await alice.getIdentity(bob.userId)).identityNeedsUserApproval()).toStrictEqual(false);
+
+            // Bob resets his cross-signing keys.
+            const newCrossSigningKeys = JSON.parse((await bob.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+            await receiveCrossSigningKeys(alice, bob.userId, newCrossSigningKeys);
+
+            const identity = await alice.getIdentity(bob.userId);
+            expect(identity.identityNeedsUserApproval()).toStrictEqual(true);
+
+            await identity.pinCurrentMasterKey();
+
+            const pinnedIdentity = await alice.getIdentity(bob.userId);
+            expect(pinnedIdentity.identityNeedsUserApproval()).toStrictEqual(false);
+            expect(JSON.parse(pinnedIdentity.masterKey).keys).toStrictEqual(newCrossSigningKeys.master_key.keys);
+        });
+    });
+
+    describe("room settings", () => {
+        test("can save and read the room settings", async () => {
+            const m = await machine();
+
+            expect(await m.getRoomSettings(room)).toBeNull();
+
+            const settings = new RoomSettings();
+            expect(settings.historyVisibility).toStrictEqual(HistoryVisibility.Shared);
+            settings.onlyAllowTrustedDevices = true;
+            settings.rotationPeriodMessages = 42n;
+            settings.historyVisibility = HistoryVisibility.Joined;
+            await m.setRoomSettings(room, settings);
+
+            const saved = await m.getRoomSettings(room);
+            expect(saved).toBeInstanceOf(RoomSettings);
+            expect(saved.algorithm).toStrictEqual(EncryptionAlgorithm.MegolmV1AesSha2);
+            expect(saved.onlyAllowTrustedDevices).toStrictEqual(true);
+            expect(saved.rotationPeriod).toBeNull();
+            expect(saved.rotationPeriodMessages).toStrictEqual(42n);
+            expect(saved.historyVisibility).toStrictEqual(HistoryVisibility.Joined);
+        });
+
+        test("refuses to change the encryption algorithm", async () => {
+            const m = await machine();
+            await m.setRoomSettings(room, new RoomSettings());
+
+            const settings = new RoomSettings();
+            settings.algorithm = EncryptionAlgorithm.OlmV1Curve25519AesSha2;
+
+            await expect(m.setRoomSettings(room, settings)).rejects.toThrow();
+            expect((await m.getRoomSettings(room)).algorithm).toStrictEqual(EncryptionAlgorithm.MegolmV1AesSha2);
+        });
+
+        test("shares the room key according to the room settings", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            const settings = new RoomSettings();
+            settings.onlyAllowTrustedDevices = true;
+            await alice.setRoomSettings(room, settings);
+
+            const { requests } = await alice.shareRoomKey(room, [bob.userId]);
+
+            const withheldRequests = requests.filter((request) => request.eventType === "m.room_key.withheld");
+            expect(withheldRequests).toHaveLength(1);
+            const { messages } = JSON.parse(withheldRequests[0].body);
+            expect(messages[bob.userId.toString()][bob.deviceId.toString()]).toMatchObject({ code: "m.unverified" });
+        });
+
+        test("rotates the room key when the history visibility of the room settings changes", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            const settings = new RoomSettings();
+            settings.historyVisibility = HistoryVisibility.Shared;
+            await alice.setRoomSettings(room, settings);
+
+            await alice.shareRoomKey(room, [bob.userId]);
+            const { sessionId } = await alice.getOutboundGroupSessionInfo(room);
+
+            await alice.shareRoomKey(room, [bob.userId]);
+            expect((await alice.getOutboundGroupSessionInfo(room)).sessionId).toStrictEqual(sessionId);
+
+            settings.historyVisibility = HistoryVisibility.Joined;
+            await alice.setRoomSettings(room, settings);
+
+            await alice.shareRoomKey(room, [bob.userId]);
+            expect((await alice.getOutboundGroupSessionInfo(room)).sessionId).not.toStrictEqual(sessionId);
+        });
+    });
+
+    describe("outbound group sessions", () => {
+        test("can read information about the outbound group session", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            expect(await alice.getOutboundGroupSessionInfo(room)).toBeNull();
+
+            await alice.shareRoomKey(room, [bob.userId]);
+
+            const info = await alice.getOutboundGroupSessionInfo(room);
+            expect(info).toBeInstanceOf(OutboundGroupSessionInfo);
+            expect(info.roomId.toString()).toStrictEqual(room.toString());
+            expect(info.sessionId).toBeDefined();
+            expect(info.creationTime).toBeLessThanOrEqual(Date.now());
+            expect(info.invalidated).toStrictEqual(false);
+
+            const sharedWithDevices = info.sharedWithDevices;
+            expect(sharedWithDevices).toHaveLength(1);
+            expect(sharedWithDevices[0]).toBeInstanceOf(SharedWithDevice);
+            expect(sharedWithDevices[0].userId.toString()).toStrictEqual(bob.userId.toString());
+            expect(sharedWithDevices[0].deviceId.toString()).toStrictEqual(bob.deviceId.toString());
+            expect(sharedWithDevices[0].messageIndex).toStrictEqual(0);
+        });
+
+        test("can discard the room key", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            expect(await alice.discardRoomKey(room)).toStrictEqual(false);
+
+            await alice.shareRoomKey(room, [bob.userId]);
+            const { sessionId } = await alice.getOutboundGroupSessionInfo(room);
+
+            expect(await alice.discardRoomKey(room)).toStrictEqual(true);
+
+            await alice.shareRoomKey(room, [bob.userId]);
+            expect((await alice.getOutboundGroupSessionInfo(room)).sessionId).not.toStrictEqual(sessionId);
+        });
+
+        test("marks the discarded group session as invalidated", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            await alice.shareRoomKey(room, [bob.userId]);
+
+            expect(await alice.discardRoomKey(room)).toStrictEqual(true);
+            expect((await alice.getOutboundGroupSessionInfo(room)).invalidated).toStrictEqual(true);
+        });
+    });
+
+    describe("room key bundles", () => {
+        test("can share the room history with an invitee", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            const upload = jest.fn(async () => "mxc://example.org/bundle");
+            expect(await alice.shareRoomKeyBundle(room, bob.userId, upload)).toEqual([]);
+            expect(upload).not.toHaveBeenCalled();
+
+            await alice.shareRoomKey(room, []);
+            const encrypted = await alice.encryptRoomEvent(room, "m.room.message", JSON.stringify({ body: "Hello" }));
+            const event = JSON.stringify({
+                type: "m.room.encrypted",
+                event_id: "$xxxxx:example.org",
+                origin_server_ts: Date.now(),
+                sender: alice.userId.toString(),
+                content: JSON.parse(encrypted),
+            });
+
+            await expect(bob.decryptRoomEvent(event, room)).rejects.toThrow();
+
+            const requests = await alice.shareRoomKeyBundle(room, bob.userId, upload);
+            expect(requests).toHaveLength(1);
+            expect(upload).toHaveBeenCalledTimes(1);
+
+            const [[encryptedBundle]] = upload.mock.calls;
+            expect(encryptedBundle).toBeInstanceOf(Uint8Array);
+
+            const { toDeviceEvents } = await deliver(alice, bob, requests[0]);
+            expect(JSON.parse(toDeviceEvents[0].rawEvent).content.file.url).toStrictEqual("mxc://example.org/bundle");
+
+            let progressed;
+            const progressListenerCalled = new Promise((resolve) => (progressed = resolve));
+            const progressListener = jest.fn(() => progressed());
+            await bob.receiveRoomKeyBundle(room, encryptedBundle, progressListener);
+
+            await progressListenerCalled;
+            expect(progressListener).toHaveBeenCalledWith(expect.any(Number), 1);
+
+            const decrypted = await bob.decryptRoomEvent(event, room);
+            expect(JSON.parse(decrypted.event).content.body).toStrictEqual("Hello");
+        });
+
+        test("cannot receive a bundle that hasn't been shared", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther();
+
+            await expect(bob.receiveRoomKeyBundle(room, new Uint8Array())).rejects.toThrow(
+                "No room key bundle has been received",
+            );
+        });
+    });
+
+    // Only available with the `experimental-encrypted-state-events` feature.
+    const describeIfEncryptedState = OlmMachine.prototype.encryptStateEvent ? describe : describe.skip;
+
+    describeIfEncryptedState("encrypted state events", () => {
+        test("can encrypt and decrypt a state event", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            const { requests } = await alice.shareRoomKey(room, [bob.userId]);
+            for (const request of requests) {
+                await deliver(alice, bob, request);
+            }
+
+            const encrypted = JSON.parse(
+                await alice.encryptStateEvent(room, "m.room.topic", "", JSON.stringify({ topic: "Secret topic" })),
+            );
+
+            expect(encrypted.algorithm).toStrictEqual("m.megolm.v1.aes-sha2");
+            expect(encrypted.ciphertext).toBeDefined();
+
+            const decrypted = await bob.decryptRoomEvent(
+                JSON.stringify({
+                    type: "m.room.encrypted",
+                    state_key: "m.room.topic:",
+                    event_id: "$xxxxx:example.org",
+                    origin_server_ts: Date.now(),
+                    sender: alice.userId.toString(),
+                    content: encrypted,
+                }),
+                room,
+            );
+
+            expect(JSON.parse(decrypted.event)).toMatchObject({
+                type: "m.room.topic",
+                content: { topic: "Secret topic" },
+            });
+        });
+
+        test("refuses a state event whose state key doesn't match", async () => {
+            const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true });
+
+            const { requests } = await alice.shareRoomKey(room, [bob.userId]);
+            for (const request of requests) {
+                await deliver(alice, bob, request);
+            }
+
+            const encrypted = JSON.parse(
+                await alice.encryptStateEvent(room, "m.room.topic", "", JSON.stringify({ topic: "Secret topic" })),
+            );
+
+            await expect(
+                bob.decryptRoomEvent(
+                    JSON.stringify({
+                        type: "m.room.encrypted",
+                        state_key: "m.room.name:",
+                        event_id: "$xxxxx:example.org",
+                        origin_server_ts: Date.now(),
+                        sender: alice.userId.toString(),
+                        content: encrypted,
+                    }),
+                    room,
+                ),
+            ).rejects.toThrow();
+        });
+    });
+
+    describe("secret gossiping", () => {
+        test("can request the missing secrets", async () => {
+            const m = await machine();
+
+            expect(await m.requestMissingSecrets()).toStrictEqual(true);
+
+            const requests = (await m.outgoingRequests()).filter(
+                (request) => request.type === RequestType.ToDevice && request.eventType === "m.secret.request",
+            );
+            expect(requests.length).toBeGreaterThan(0);
+
+            const messages = JSON.parse(requests[0].body).messages;
+            expect(Object.keys(messages)).toStrictEqual([user.toString()]);
+            expect(Object.values(messages[user.toString()])[0]).toMatchObject({ action: "request" });
+        });
+
+        test("has an empty secret inbox by default", async () => {
+            const m = await machine();
+
+            expect(await m.getSecretsFromInbox("m.megolm_backup.v1")).toStrictEqual([]);
+            await m.deleteSecretsFromInbox("m.megolm_backup.v1");
+            expect(await m.getSecretsFromInbox("m.megolm_backup.v1")).toStrictEqual([]);
+        });
+    });
+
     describe("secret storage", () => {
         test("can save to and load from secret storage", async () => {
             // create cross-signing keys on one machine
@@ -655,38 +1630,141 @@ describe(OlmMachine.name, () => {
 
             // create a new machine and load the public cross-signing keys
             const m2 = await machine();
-            const outgoingRequests = await m2.outgoingRequests();
-            const keysUploadResponse = JSON.stringify({
-                one_time_key_counts: {
-                    signed_curve25519: 100,
-                },
-            });
-            await m2.markRequestAsSent(outgoingRequests[0].id, outgoingRequests[0].type, keysUploadResponse);
-            const deviceKeys = JSON.parse(outgoingRequests[0].body).device_keys;
-
-            const keysQueryResponse = JSON.stringify({
-                device_keys: {
-                    "@alice:example.org": {
-                        foobar: deviceKeys,
-                    },
-                },
-                failures: {},
-                master_keys: {
-                    "@alice:example.org": crossSigningKeys.master_key,
-                },
-                self_signing_keys: {
-                    "@alice:example.org": crossSigningKeys.self_signing_key,
-                },
-                user_signing_keys: {
-                    "@alice:example.org": crossSigningKeys.user_signing_key,
-                },
-            });
-            await m2.markRequestAsSent(outgoingRequests[1].id, outgoingRequests[1].type, keysQueryResponse);
+            await learnOwnCrossSigningKeys(m2, crossSigningKeys);
 
             // After we load the secrets from secret storage, we should have the
             // signed device keys available for upload
             const request = await m2.importSecretsFromSecretStorage(secretStorageKey, secretEvents);
             expect(JSON.parse(request.body).signed_keys).toHaveProperty(["@alice:example.org", "foobar"]);
+
+            // There was no backup decryption key to export.
+            expect(secretEvents.backupKey).toBeNull();
+            expect((await m2.getBackupKeys()).decryptionKeyBase64).toBeNull();
+        });
+
+        test("can bootstrap secret storage", async () => {
+            const m = await machine();
+            const result = await m.bootstrapSecretStorage("passphrase");
+
+            expect(result).toBeInstanceOf(SecretStorageBootstrapResult);
+            expect(result.crossSigningBootstrapRequests).toBeInstanceOf(CrossSigningBootstrapRequests);
+
+            const secretStorageKey = result.secretStorageKey;
+            expect(secretStorageKey).toBeInstanceOf(SecretStorageKey);
+            expect(result.secretStorageKey).toBe(secretStorageKey);
+
+            // The new backup decryption key is saved, without a backup version.
+            const backupKeys = await m.getBackupKeys();
+            expect(backupKeys.decryptionKeyBase64).toStrictEqual(result.backupDecryptionKey.toBase64());
+            expect(backupKeys.backupVersion).toBeNull();
+
+            const accountData = Object.fromEntries(
+                result.accountData.map((event) => {
+                    expect(event).toBeInstanceOf(AccountDataEvent);
+                    return [event.eventType, event.content];
+                }),
+            );
+            expect(Object.keys(accountData).sort()).toStrictEqual(
+                [
+                    secretStorageKey.eventType(),
+                    "m.secret_storage.default_key",
+                    "m.cross_signing.master",
+                    "m.cross_signing.self_signing",
+                    "m.cross_signing.user_signing",
+                    "m.megolm_backup.v1",
+                ].sort(),
+            );
+            expect(JSON.parse(accountData["m.secret_storage.default_key"])).toStrictEqual({
+                key: secretStorageKey.keyId(),
+            });
+
+            // The key can be restored from the passphrase.
+            const restoredKey = SecretStorageKey.fromAccountData(
+                "passphrase",
+                secretStorageKey.eventType(),
+                accountData[secretStorageKey.eventType()],
+            );
+            expect(restoredKey.keyId()).toStrictEqual(secretStorageKey.keyId());
+
+            expect(restoredKey.decrypt(accountData["m.megolm_backup.v1"], "m.megolm_backup.v1")).toStrictEqual(
+                result.backupDecryptionKey.toBase64(),
+            );
+            expect(restoredKey.decrypt(accountData["m.cross_signing.master"], "m.cross_signing.master")).toBeDefined();
+
+            const backupVersionRequest = JSON.parse(result.backupVersionRequest);
+            expect(backupVersionRequest.algorithm).toStrictEqual("m.megolm_backup.v1.curve25519-aes-sha2");
+            expect(backupVersionRequest.auth_data.public_key).toStrictEqual(
+                result.backupDecryptionKey.megolmV1PublicKey.publicKeyBase64,
+            );
+            expect(backupVersionRequest.auth_data.signatures).toHaveProperty(user.toString());
+        });
+
+        test("can bootstrap secret storage with a random key", async () => {
+            const m = await machine();
+            const result = await m.bootstrapSecretStorage();
+
+            expect(result.accountData).toHaveLength(6);
+            expect((await m.crossSigningStatus()).hasMaster).toStrictEqual(true);
+        });
+
+        test("reuses the existing cross-signing keys unless asked to reset them", async () => {
+            const m = await machine();
+            const masterKey = (requests) => JSON.parse(requests.uploadSigningKeysReq).master_key;
+            const existingMasterKey = masterKey(await m.bootstrapCrossSigning(true));
+
+            const result = await m.bootstrapSecretStorage();
+            expect(masterKey(result.crossSigningBootstrapRequests)).toStrictEqual(existingMasterKey);
+
+            const resetResult = await m.bootstrapSecretStorage(null, true);
+            expect(masterKey(resetResult.crossSigningBootstrapRequests)).not.toStrictEqual(existingMasterKey);
+        });
+
+        test("can save the backup decryption key to and load it from secret storage", async () => {
+            const m = await machine();
+            const crossSigningKeys = JSON.parse((await m.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+            const backupKey = BackupDecryptionKey.createRandomKey();
+            await m.saveBackupDecryptionKey(backupKey, "1");
+
+            const secretStorageKey = SecretStorageKey.createRandomKey();
+            const secretEvents = await m.exportSecretsForSecretStorage(secretStorageKey);
+            expect(secretEvents.backupKey).toBeDefined();
+            expect(secretStorageKey.decrypt(secretEvents.backupKey, "m.megolm_backup.v1")).toStrictEqual(
+                backupKey.toBase64(),
+            );
+
+            // Round-trip through account data.
+            const items = new SecretStorageItems({
+                masterKey: secretEvents.masterKey,
+                selfSigningKey: secretEvents.selfSigningKey,
+                userSigningKey: secretEvents.userSigningKey,
+                backupKey: secretEvents.backupKey,
+            });
+
+            const m2 = await machine();
+            await learnOwnCrossSigningKeys(m2, crossSigningKeys);
+
+            // The key is checked against the backup it's said to belong to.
+            const otherBackupInfo = {
+                algorithm: "m.megolm_backup.v1.curve25519-aes-sha2",
+                auth_data: {
+                    public_key: BackupDecryptionKey.createRandomKey().megolmV1PublicKey.publicKeyBase64,
+                    signatures: {},
+                },
+            };
+            await expect(
+                m2.importSecretsFromSecretStorage(secretStorageKey, items, "1", JSON.stringify(otherBackupInfo)),
+            ).rejects.toThrow("The backup decryption key doesn't match the public key of the backup");
+            expect((await m2.getBackupKeys()).decryptionKeyBase64).toBeNull();
+
+            const backupInfo = {
+                algorithm: "m.megolm_backup.v1.curve25519-aes-sha2",
+                auth_data: { public_key: backupKey.megolmV1PublicKey.publicKeyBase64, signatures: {} },
+            };
+            await m2.importSecretsFromSecretStorage(secretStorageKey, items, "1", JSON.stringify(backupInfo));
+
+            const backupKeys = await m2.getBackupKeys();
+            expect(backupKeys.decryptionKeyBase64).toStrictEqual(backupKey.toBase64());
+            expect(backupKeys.backupVersion).toStrictEqual("1");
         });
     });
 });
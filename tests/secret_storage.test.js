@@ -0,0 +1,107 @@
+const { AccountDataEvent, SecretStorage, SecretStorageKey } = require("../");
+
+describe(SecretStorage.name, () => {
+    test("is empty by default", () => {
+        const storage = new SecretStorage();
+
+        expect(storage.secretNames()).toStrictEqual([]);
+        expect(storage.accountData()).toStrictEqual([]);
+    });
+
+    test("can set and get secrets", () => {
+        const key = SecretStorageKey.createRandomKey();
+        const storage = new SecretStorage();
+
+        storage.setSecret("org.example.first", "first secret", [key]);
+        storage.setSecret("org.example.second", "second secret", [key]);
+
+        expect(storage.secretNames()).toStrictEqual(["org.example.first", "org.example.second"]);
+        expect(storage.getSecret("org.example.first", key)).toStrictEqual("first secret");
+        expect(storage.getSecret("org.example.second", key)).toStrictEqual("second secret");
+        expect(storage.getSecret("org.example.unknown", key)).toBeNull();
+    });
+
+    test("cannot set a secret without a key", () => {
+        const storage = new SecretStorage();
+
+        expect(() => storage.setSecret("org.example.secret", "secret", [])).toThrow();
+    });
+
+    test("can encrypt a secret with several keys", () => {
+        const key1 = SecretStorageKey.createRandomKey();
+        const key2 = SecretStorageKey.createRandomKey();
+        const storage = new SecretStorage();
+
+        storage.setSecret("org.example.secret", "secret", [key1, key2]);
+
+        expect(storage.keyIds("org.example.secret").sort()).toStrictEqual([key1.keyId(), key2.keyId()].sort());
+        expect(storage.getSecret("org.example.secret", key1)).toStrictEqual("secret");
+        expect(storage.getSecret("org.example.secret", key2)).toStrictEqual("secret");
+    });
+
+    test("cannot decrypt a secret with the wrong key", () => {
+        const storage = new SecretStorage();
+        storage.setSecret("org.example.secret", "secret", [SecretStorageKey.createRandomKey()]);
+
+        expect(() => storage.getSecret("org.example.secret", SecretStorageKey.createRandomKey())).toThrow();
+    });
+
+    test("can remove a secret", () => {
+        const key = SecretStorageKey.createRandomKey();
+        const storage = new SecretStorage();
+        storage.setSecret("org.example.secret", "secret", [key]);
+
+        expect(storage.removeSecret("org.example.secret")).toStrictEqual(true);
+        expect(storage.removeSecret("org.example.secret")).toStrictEqual(false);
+        expect(storage.secretNames()).toStrictEqual([]);
+    });
+
+    test("can emit and load the account data", () => {
+        const key = SecretStorageKey.createRandomKey();
+        const storage = new SecretStorage();
+        storage.setSecret("org.example.secret", "secret", [key]);
+
+        const accountData = storage.accountData();
+        expect(accountData).toHaveLength(1);
+        expect(accountData[0]).toBeInstanceOf(AccountDataEvent);
+        expect(accountData[0].eventType).toStrictEqual("org.example.secret");
+        expect(JSON.parse(accountData[0].content).encrypted).toHaveProperty([key.keyId()]);
+
+        // The content is compatible with `SecretStorageKey.decrypt`.
+        expect(key.decrypt(accountData[0].content, "org.example.secret")).toStrictEqual("secret");
+
+        const loaded = new SecretStorage();
+        loaded.addEncryptedSecret(accountData[0].eventType, accountData[0].content);
+        expect(loaded.getSecret("org.example.secret", key)).toStrictEqual("secret");
+    });
+
+    test("merges the encrypted data of a secret", () => {
+        const key1 = SecretStorageKey.createRandomKey();
+        const key2 = SecretStorageKey.createRandomKey();
+        const storage = new SecretStorage();
+
+        storage.addEncryptedSecret("org.example.secret", key1.encrypt("secret", "org.example.secret"));
+        storage.addEncryptedSecret("org.example.secret", key2.encrypt("secret", "org.example.secret"));
+
+        expect(storage.keyIds("org.example.secret")).toHaveLength(2);
+    });
+
+    test("can rotate a key", () => {
+        const oldKey = SecretStorageKey.createRandomKey();
+        const otherKey = SecretStorageKey.createRandomKey();
+        const newKey = SecretStorageKey.createRandomKey();
+        const storage = new SecretStorage();
+
+        storage.setSecret("org.example.first", "first secret", [oldKey, otherKey]);
+        storage.setSecret("org.example.second", "second secret", [otherKey]);
+
+        storage.rotateKey(oldKey, newKey);
+
+        expect(storage.keyIds("org.example.first").sort()).toStrictEqual([otherKey.keyId(), newKey.keyId()].sort());
+        expect(storage.getSecret("org.example.first", newKey)).toStrictEqual("first secret");
+        expect(() => storage.getSecret("org.example.first", oldKey)).toThrow();
+
+        expect(storage.keyIds("org.example.second")).toStrictEqual([otherKey.keyId()]);
+        expect(storage.getSecret("org.example.second", otherKey)).toStrictEqual("second secret");
+    });
+});
@@ -0,0 +1,459 @@
+const {
+    BackupDecryptionKey,
+    CustomStore,
+    Device,
+    DeviceId,
+    EncryptionSettings,
+    OlmMachine,
+    ProcessedToDeviceEventType,
+    RoomId,
+    StoreType,
+    UserId,
+} = require("../");
+const { deliver, machinesThatKnowEachOther } = require("./helpers");
+
+// A custom store keeping everything in memory.
+class MemoryStore {
+    constructor() {
+        this.account = null;
+        this.privateIdentity = null;
+        this.tables = {};
+    }
+
+    table(name) {
+        this.tables[name] ??= new Map();
+
+        return this.tables[name];
+    }
+
+    async loadAccount() {
+        return this.account;
+    }
+
+    async loadIdentity() {
+        return this.privateIdentity;
+    }
+
+    async saveChanges(changes) {
+        for (const account of changes.account) this.account = account;
+        for (const identity of changes.privateIdentity) this.privateIdentity = identity;
+
+        for (const { senderKey, sessionId, pickle } of changes.sessions) {
+            this.table("sessions").set(`${senderKey}|${sessionId}`, { senderKey, pickle });
+        }
+
+        for (const session of changes.inboundGroupSessions) {
+            this.table("inboundGroupSessions").set(`${session.roomId}|${session.sessionId}`, session);
+        }
+
+        for (const { roomId, pickle } of changes.outboundGroupSessions) {
+            this.table("outboundGroupSessions").set(roomId, pickle);
+        }
+
+        for (const { senderKey, hash } of changes.messageHashes) {
+            this.table("messageHashes").set(`${senderKey}|${hash}`, true);
+        }
+
+        for (const { userId, deviceId, device } of changes.devices) {
+            this.table("devices").set(`${userId}|${deviceId}`, { userId, device });
+        }
+
+        for (const { userId, deviceId } of changes.deletedDevices) {
+            this.table("devices").delete(`${userId}|${deviceId}`);
+        }
+
+        for (const { userId, identity } of changes.userIdentities) {
+            this.table("userIdentities").set(userId, identity);
+        }
+
+        for (const gossipRequest of changes.gossipRequests) {
+            this.table("gossipRequests").set(gossipRequest.requestId, gossipRequest);
+        }
+
+        for (const requestId of changes.deletedGossipRequests) {
+            this.table("gossipRequests").delete(requestId);
+        }
+
+        for (const { secretName, secret } of changes.secrets) {
+            this.table("secrets").set(secretName, [...(this.table("secrets").get(secretName) ?? []), secret]);
+        }
+
+        for (const secretName of changes.deletedSecrets) {
+            this.table("secrets").delete(secretName);
+        }
+
+        for (const { roomId, sessionId, info } of changes.withheldSessions) {
+            this.table("withheldSessions").set(`${roomId}|${sessionId}`, info);
+        }
+
+        for (const { roomId, settings } of changes.roomSettings) {
+            this.table("roomSettings").set(roomId, settings);
+        }
+
+        for (const { roomId, userId, bundle } of changes.roomKeyBundles) {
+            this.table("roomKeyBundles").set(`${roomId}|${userId}`, bundle);
+        }
+
+        for (const trackedUser of changes.trackedUsers) {
+            this.table("trackedUsers").set(trackedUser.userId, trackedUser);
+        }
+
+        for (const { key, value } of changes.customValues) {
+            if (value === null) {
+                this.table("customValues").delete(key);
+            } else {
+                this.table("customValues").set(key, value);
+            }
+        }
+    }
+
+    async getSessions(senderKey) {
+        return [...this.table("sessions").values()]
+            .filter((session) => session.senderKey === senderKey)
+            .map((session) => session.pickle);
+    }
+
+    async getInboundGroupSession(roomId, sessionId) {
+        return this.table("inboundGroupSessions").get(`${roomId}|${sessionId}`) ?? null;
+    }
+
+    async getInboundGroupSessions() {
+        return [...this.table("inboundGroupSessions").values()];
+    }
+
+    async getInboundGroupSessionCounts(backupVersion) {
+        const sessions = [...this.table("inboundGroupSessions").values()];
+        const backedUp = sessions.filter((session) =>
+            backupVersion === null ? session.backedUp : session.backedUpToVersion === backupVersion,
+        );
+
+        return { total: sessions.length, backedUp: backedUp.length };
+    }
+
+    async getInboundGroupSessionsForDeviceBatch(senderKey, senderDataType, afterSessionId, limit) {
+        return [...this.table("inboundGroupSessions").values()]
+            .filter(
+                (session) =>
+                    session.senderKey === senderKey &&
+                    session.senderDataType === senderDataType &&
+                    (afterSessionId === null || session.sessionId > afterSessionId),
+            )
+            .sort((a, b) => (a.sessionId < b.sessionId ? -1 : 1))
+            .slice(0, limit);
+    }
+
+    async getInboundGroupSessionsForBackup(backupVersion, limit) {
+        return [...this.table("inboundGroupSessions").values()]
+            .filter((session) => session.backedUpToVersion !== backupVersion)
+            .slice(0, limit);
+    }
+
+    async markInboundGroupSessionsAsBackedUp(backupVersion, sessions) {
+        for (const { roomId, sessionId } of sessions) {
+            const session = this.table("inboundGroupSessions").get(`${roomId}|${sessionId}`);
+
+            if (session) {
+                session.backedUp = true;
+                session.backedUpToVersion = backupVersion;
+            }
+        }
+    }
+
+    async resetBackupState() {
+        for (const session of this.table("inboundGroupSessions").values()) {
+            session.backedUp = false;
+            session.backedUpToVersion = null;
+        }
+    }
+
+    async getOutboundGroupSession(roomId) {
+        return this.table("outboundGroupSessions").get(roomId) ?? null;
+    }
+
+    async loadTrackedUsers() {
+        return [...this.table("trackedUsers").values()];
+    }
+
+    async getDevice(userId, deviceId) {
+        return this.table("devices").get(`${userId}|${deviceId}`)?.device ?? null;
+    }
+
+    async getUserDevices(userId) {
+        return [...this.table("devices").values()]
+            .filter((device) => device.userId === userId)
+            .map((device) => device.device);
+    }
+
+    async getUserIdentity(userId) {
+        return this.table("userIdentities").get(userId) ?? null;
+    }
+
+    async isMessageKnown(senderKey, hash) {
+        return this.table("messageHashes").has(`${senderKey}|${hash}`);
+    }
+
+    async getGossipRequests() {
+        return [...this.table("gossipRequests").values()].map((gossipRequest) => gossipRequest.request);
+    }
+
+    async getGossipRequest(requestId) {
+        return this.table("gossipRequests").get(requestId)?.request ?? null;
+    }
+
+    async getGossipRequestByInfo(infoKey) {
+        const gossipRequest = [...this.table("gossipRequests").values()].find(
+            (gossipRequest) => gossipRequest.infoKey === infoKey,
+        );
+
+        return gossipRequest?.request ?? null;
+    }
+
+    async getSecretsFromInbox(secretName) {
+        return this.table("secrets").get(secretName) ?? [];
+    }
+
+    async getWithheldInfo(roomId, sessionId) {
+        return this.table("withheldSessions").get(`${roomId}|${sessionId}`) ?? null;
+    }
+
+    async getRoomSettings(roomId) {
+        return this.table("roomSettings").get(roomId) ?? null;
+    }
+
+    async getReceivedRoomKeyBundleData(roomId, userId) {
+        return this.table("roomKeyBundles").get(`${roomId}|${userId}`) ?? null;
+    }
+
+    async getCustomValue(key) {
+        return this.table("customValues").get(key) ?? null;
+    }
+}
+
+describe(CustomStore.name, () => {
+    const user = new UserId("@alice:example.org");
+    const device = new DeviceId("foobar");
+
+    test("requires all the methods to be implemented", () => {
+        expect(() => new CustomStore({})).toThrow(/loadAccount/);
+    });
+
+    test("is required by `StoreType.Custom`", async () => {
+        await expect(OlmMachine.initialize(user, device, null, null, StoreType.Custom)).rejects.toThrow();
+    });
+
+    test("can only be used with `StoreType.Custom`", async () => {
+        const store = new CustomStore(new MemoryStore());
+
+        for (const storeType of [undefined, StoreType.Sqlite, StoreType.Memory]) {
+            await expect(OlmMachine.initialize(user, device, null, null, storeType, store)).rejects.toThrow(
+                "A `CustomStore` can only be given with `StoreType.Custom`",
+            );
+        }
+    });
+
+    test("can be used to persist an `OlmMachine`", async () => {
+        const memoryStore = new MemoryStore();
+        const store = new CustomStore(memoryStore);
+
+        const m1 = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+        const identityKeys = m1.identityKeys;
+        m1.close();
+
+        expect(memoryStore.account).not.toBeNull();
+
+        const m2 = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+
+        expect(m2.identityKeys.ed25519.toBase64()).toStrictEqual(identityKeys.ed25519.toBase64());
+        expect(m2.identityKeys.curve25519.toBase64()).toStrictEqual(identityKeys.curve25519.toBase64());
+        expect(await m2.getDevice(user, device)).toBeInstanceOf(Device);
+
+        m2.close();
+    });
+
+    test("persists inbound group sessions", async () => {
+        const memoryStore = new MemoryStore();
+        const store = new CustomStore(memoryStore);
+        const room = new RoomId("!baz:matrix.org");
+
+        const m1 = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+        await m1.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+        expect(memoryStore.table("inboundGroupSessions").size).toStrictEqual(1);
+
+        const [{ sessionId }] = memoryStore.table("inboundGroupSessions").values();
+        const exported = await m1.exportRoomKeysForSession(room.toString(), sessionId);
+        m1.close();
+
+        const m2 = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+
+        expect((await m2.roomKeyCounts()).total).toStrictEqual(1);
+        expect(await m2.exportRoomKeysForSession(room.toString(), sessionId)).toStrictEqual(exported);
+
+        m2.close();
+    });
+
+    test("tracks the backup state of inbound group sessions", async () => {
+        const memoryStore = new MemoryStore();
+        const store = new CustomStore(memoryStore);
+        const room = new RoomId("!baz:matrix.org");
+
+        const m1 = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+        await m1.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+        const keyBackupKey = BackupDecryptionKey.createRandomKey();
+        await m1.enableBackupV1(keyBackupKey.megolmV1PublicKey.publicKeyBase64, "1");
+
+        const backupRequest = await m1.backupRoomKeys();
+        expect(Object.keys(JSON.parse(backupRequest.body).rooms)).toEqual([room.toString()]);
+
+        await m1.markRequestAsSent(backupRequest.id, backupRequest.type, '{"etag":"1","count":1}');
+
+        expect(await m1.roomKeyCounts()).toMatchObject({ total: 1, backedUp: 1 });
+        expect([...memoryStore.table("inboundGroupSessions").values()]).toMatchObject([
+            { backedUp: true, backedUpToVersion: "1" },
+        ]);
+
+        // Everything has been backed up.
+        expect(await m1.backupRoomKeys()).toBeNull();
+        m1.close();
+
+        // The backup state survives a restart.
+        const m2 = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+        expect(await m2.roomKeyCounts()).toMatchObject({ total: 1, backedUp: 1 });
+
+        await m2.disableBackup();
+
+        expect(await m2.roomKeyCounts()).toMatchObject({ total: 1, backedUp: 0 });
+        expect([...memoryStore.table("inboundGroupSessions").values()]).toMatchObject([
+            { backedUp: false, backedUpToVersion: null },
+        ]);
+
+        m2.close();
+    });
+
+    test("keeps the backup state of inbound group sessions saved again", async () => {
+        const memoryStore = new MemoryStore();
+        const store = new CustomStore(memoryStore);
+        const room = new RoomId("!baz:matrix.org");
+
+        const [alice, bob, deviceKeys] = await machinesThatKnowEachOther({ withOlmSession: true, bobStore: store });
+        const { requests } = await alice.shareRoomKey(room, [bob.userId], new EncryptionSettings());
+        await deliver(alice, bob, requests[0]);
+
+        const keyBackupKey = BackupDecryptionKey.createRandomKey();
+        await bob.enableBackupV1(keyBackupKey.megolmV1PublicKey.publicKeyBase64, "1");
+        const backupRequest = await bob.backupRoomKeys();
+        await bob.markRequestAsSent(backupRequest.id, backupRequest.type, '{"etag":"1","count":1}');
+
+        const [{ senderDataType }] = memoryStore.table("inboundGroupSessions").values();
+
+        // Learning that Alice's device is cross-signed updates the sender data
+        // of the session, which saves it again.
+        const aliceId = alice.userId.toString();
+        const { uploadSigningKeysReq, uploadSignaturesReq } = await alice.bootstrapCrossSigning(true);
+        const signingKeys = JSON.parse(uploadSigningKeysReq);
+        const aliceDeviceKeys = deviceKeys[aliceId][alice.deviceId.toString()];
+        const signedDeviceKeys = JSON.parse(uploadSignaturesReq.body).signed_keys[aliceId][alice.deviceId.toString()];
+
+        const request = bob.queryKeysForUsers([alice.userId]);
+        await bob.markRequestAsSent(
+            request.id,
+            request.type,
+            JSON.stringify({
+                device_keys: {
+                    [aliceId]: {
+                        [alice.deviceId.toString()]: {
+                            ...aliceDeviceKeys,
+                            signatures: {
+                                [aliceId]: {
+                                    ...aliceDeviceKeys.signatures[aliceId],
+                                    ...signedDeviceKeys.signatures[aliceId],
+                                },
+                            },
+                        },
+                    },
+                },
+                master_keys: { [aliceId]: signingKeys.master_key },
+                self_signing_keys: { [aliceId]: signingKeys.self_signing_key },
+                failures: {},
+            }),
+        );
+
+        const [session] = memoryStore.table("inboundGroupSessions").values();
+        expect(session.senderDataType).not.toStrictEqual(senderDataType);
+        expect(session).toMatchObject({ backedUp: true, backedUpToVersion: "1" });
+        expect(await bob.backupRoomKeys()).toBeNull();
+    });
+
+    test("base64-encodes all the custom values", async () => {
+        const memoryStore = new MemoryStore();
+        const store = new CustomStore(memoryStore);
+
+        const m = await OlmMachine.initialize(user, device, null, null, StoreType.Custom, store);
+        await m.saveBackupDecryptionKey(BackupDecryptionKey.createRandomKey(), "1");
+
+        expect((await m.getBackupKeys()).backupVersion).toStrictEqual("1");
+        // The backup version is saved like any other custom value, as unpadded base64.
+        expect([...memoryStore.table("customValues").values()]).toContain("MQ");
+
+        m.close();
+    });
+
+    test("doesn't decrypt Olm messages if `isMessageKnown` is malformed", async () => {
+        const memoryStore = new MemoryStore();
+        memoryStore.isMessageKnown = async () => "yes";
+
+        const store = new CustomStore(memoryStore);
+        const [alice, bob] = await machinesThatKnowEachOther({ withOlmSession: true, bobStore: store });
+        const bobDevice = await alice.getDevice(bob.userId, bob.deviceId);
+
+        const { toDeviceRequest } = await alice.encryptToDeviceEvent("org.example.ping", "{}", [bobDevice]);
+
+        const { toDeviceEvents } = await deliver(alice, bob, toDeviceRequest);
+
+        expect(toDeviceEvents).toHaveLength(1);
+        expect(toDeviceEvents[0].type).toStrictEqual(ProcessedToDeviceEventType.UnableToDecrypt);
+    });
+});
+
+describe("StoreType.Memory", () => {
+    const user = new UserId("@alice:example.org");
+    const device = new DeviceId("foobar");
+
+    test("can snapshot and restore an `OlmMachine`", async () => {
+        const m1 = await OlmMachine.initialize(user, device, null, null, StoreType.Memory);
+        const identityKeys = m1.identityKeys;
+        const snapshot = m1.snapshot("hello");
+        m1.close();
+
+        expect(snapshot).toBeInstanceOf(Uint8Array);
+
+        const m2 = await OlmMachine.initializeFromSnapshot(snapshot, "hello");
+
+        expect(m2.userId.toString()).toStrictEqual(user.toString());
+        expect(m2.deviceId.toString()).toStrictEqual(device.toString());
+        expect(m2.identityKeys.ed25519.toBase64()).toStrictEqual(identityKeys.ed25519.toBase64());
+        expect(m2.identityKeys.curve25519.toBase64()).toStrictEqual(identityKeys.curve25519.toBase64());
+
+        // A restored machine can be snapshotted again.
+        expect(m2.snapshot("world")).toBeInstanceOf(Uint8Array);
+    });
+
+    test("cannot restore a snapshot with the wrong passphrase", async () => {
+        const m = await OlmMachine.initialize(user, device, null, null, StoreType.Memory);
+
+        await expect(OlmMachine.initializeFromSnapshot(m.snapshot("hello"), "world")).rejects.toThrow();
+    });
+
+    test("cannot be given a store path", async () => {
+        await expect(OlmMachine.initialize(user, device, "/tmp/store", null, StoreType.Memory)).rejects.toThrow(
+            "A store path or passphrase can only be given with `StoreType.Sqlite`",
+        );
+    });
+
+    test("cannot snapshot other stores", async () => {
+        const m = await OlmMachine.initialize(user, device);
+
+        expect(() => m.snapshot("hello")).toThrow(/StoreType.Memory/);
+    });
+});
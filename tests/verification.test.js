@@ -0,0 +1,239 @@
+const {
+    OlmMachine,
+    VerificationMethod,
+    VerificationRequest,
+    VerificationRequestPhase,
+    Sas,
+    Emoji,
+    ConfirmVerificationResult,
+    Qr,
+} = require("../");
+const { deliver, deliverOutgoing, machinesThatKnowEachOther } = require("./helpers");
+
+// Only available with the `qrcode` feature.
+const describeIfQrCode = OlmMachine.prototype.getQr ? describe : describe.skip;
+
+// Set up the cross-signing keys of two machines returned by
+// `machinesThatKnowEachOther`, and let each machine learn about the other
+// user's identity.
+async function crossSignEachOther(alice, bob, deviceKeys) {
+    const signingKeys = {};
+
+    for (const m of [alice, bob]) {
+        signingKeys[m.userId.toString()] = JSON.parse((await m.bootstrapCrossSigning(true)).uploadSigningKeysReq);
+    }
+
+    for (const [m, other] of [[alice, bob], [bob, alice]]) {
+        const otherId = other.userId.toString();
+        const request = m.queryKeysForUsers([other.userId]);
+
+        await m.markRequestAsSent(
+            request.id,
+            request.type,
+            JSON.stringify({
+                device_keys: { [otherId]: deviceKeys[otherId] },
+                master_keys: { [otherId]: signingKeys[otherId].master_key },
+                self_signing_keys: { [otherId]: signingKeys[otherId].self_signing_key },
+                failures: {},
+            }),
+        );
+    }
+}
+
+describe("VerificationMethod", () => {
+    test("has the correct variant values", () => {
+        expect(VerificationMethod.SasV1).toStrictEqual(0);
+        expect(VerificationMethod.QrCodeScanV1).toStrictEqual(1);
+        expect(VerificationMethod.QrCodeShowV1).toStrictEqual(2);
+        expect(VerificationMethod.ReciprocateV1).toStrictEqual(3);
+    });
+});
+
+describe(VerificationRequest.name, () => {
+    test("cannot be instantiated", () => {
+        expect(() => {
+            new VerificationRequest();
+        }).toThrow();
+    });
+});
+
+describe(Sas.name, () => {
+    test("cannot be instantiated", () => {
+        expect(() => {
+            new Sas();
+        }).toThrow();
+    });
+});
+
+describe("interactive verification", () => {
+    test("can verify two devices with emojis", async () => {
+        const [alice, bob] = await machinesThatKnowEachOther();
+
+        // Alice requests a verification with Bob's device.
+        const { verificationRequest: aliceRequest, request: outgoingRequest } = await alice.requestVerification(
+            bob.userId,
+            bob.deviceId,
+            [VerificationMethod.SasV1],
+        );
+
+        expect(aliceRequest).toBeInstanceOf(VerificationRequest);
+        expect(aliceRequest.weStarted).toStrictEqual(true);
+        expect(aliceRequest.phase).toStrictEqual(VerificationRequestPhase.Created);
+        expect(aliceRequest.otherUserId.toString()).toStrictEqual(bob.userId.toString());
+
+        const flowId = aliceRequest.flowId;
+        expect(alice.getVerificationRequest(bob.userId, flowId)).toBeInstanceOf(VerificationRequest);
+
+        await deliver(alice, bob, outgoingRequest);
+
+        // Bob receives the request and accepts it.
+        const bobRequest = bob.getVerificationRequest(alice.userId, flowId);
+
+        expect(bobRequest).toBeInstanceOf(VerificationRequest);
+        expect(bobRequest.weStarted).toStrictEqual(false);
+        expect(bobRequest.phase).toStrictEqual(VerificationRequestPhase.Requested);
+        expect(bobRequest.theirSupportedMethods).toEqual([VerificationMethod.SasV1]);
+        expect(bob.getVerificationRequests(alice.userId)).toHaveLength(1);
+
+        await deliver(bob, alice, bobRequest.accept([VerificationMethod.SasV1]));
+
+        expect(aliceRequest.isReady).toStrictEqual(true);
+
+        // Alice starts a SAS verification, Bob accepts it.
+        const { sas: aliceSas, request: startRequest } = await aliceRequest.startSas();
+
+        expect(aliceSas).toBeInstanceOf(Sas);
+
+        await deliver(alice, bob, startRequest);
+
+        const bobSas = bob.getSas(alice.userId, flowId);
+
+        expect(bobSas).toBeInstanceOf(Sas);
+        expect(bobSas.supportsEmoji).toStrictEqual(true);
+        expect(bobSas.emoji()).toBeNull();
+
+        await deliver(bob, alice, bobSas.accept());
+
+        // Exchange the ephemeral keys.
+        await deliverOutgoing(alice, bob);
+        await deliverOutgoing(bob, alice);
+
+        expect(aliceSas.canBePresented).toStrictEqual(true);
+        expect(bobSas.canBePresented).toStrictEqual(true);
+
+        const aliceEmojis = aliceSas.emoji();
+        const bobEmojis = bobSas.emoji();
+
+        expect(aliceEmojis).toHaveLength(7);
+        expect(aliceEmojis[0]).toBeInstanceOf(Emoji);
+        expect(aliceEmojis.map((emoji) => emoji.symbol)).toEqual(bobEmojis.map((emoji) => emoji.symbol));
+        expect(aliceSas.emojiIndex()).toEqual(bobSas.emojiIndex());
+        expect(aliceSas.decimals()).toHaveLength(3);
+        expect(aliceSas.decimals()).toEqual(bobSas.decimals());
+
+        // Both sides confirm that the emojis match, and exchange their MACs.
+        const aliceConfirmation = await aliceSas.confirm();
+
+        expect(aliceConfirmation).toBeInstanceOf(ConfirmVerificationResult);
+        expect(aliceConfirmation.requests.map((request) => request.eventType)).toEqual(["m.key.verification.mac"]);
+        expect(aliceSas.haveWeConfirmed).toStrictEqual(true);
+        expect(aliceSas.isDone).toStrictEqual(false);
+
+        for (const request of aliceConfirmation.requests) {
+            await deliver(alice, bob, request);
+        }
+
+        const bobConfirmation = await bobSas.confirm();
+
+        expect(bobConfirmation.requests.map((request) => request.eventType)).toEqual([
+            "m.key.verification.mac",
+            "m.key.verification.done",
+        ]);
+
+        for (const request of bobConfirmation.requests) {
+            await deliver(bob, alice, request);
+        }
+
+        // Alice sends her `m.key.verification.done` event in turn.
+        await deliverOutgoing(alice, bob);
+
+        expect(aliceSas.isDone).toStrictEqual(true);
+        expect(bobSas.isDone).toStrictEqual(true);
+        expect(aliceRequest.isDone).toStrictEqual(true);
+        expect(bobRequest.isDone).toStrictEqual(true);
+        expect((await alice.getDevice(bob.userId, bob.deviceId)).isVerified()).toStrictEqual(true);
+        expect((await bob.getDevice(alice.userId, alice.deviceId)).isVerified()).toStrictEqual(true);
+    });
+
+    test("can cancel a verification request", async () => {
+        const [alice, bob] = await machinesThatKnowEachOther();
+
+        const { verificationRequest, request } = await alice.requestVerification(bob.userId, bob.deviceId);
+
+        await deliver(alice, bob, request);
+        await deliver(alice, bob, verificationRequest.cancel());
+
+        expect(verificationRequest.isCancelled).toStrictEqual(true);
+        expect(verificationRequest.cancelInfo.cancelledByUs).toStrictEqual(true);
+
+        const bobRequest = bob.getVerificationRequest(alice.userId, verificationRequest.flowId);
+
+        expect(bobRequest.isCancelled).toStrictEqual(true);
+        expect(bobRequest.phase).toStrictEqual(VerificationRequestPhase.Cancelled);
+        expect(bobRequest.cancelInfo.cancelCode).toStrictEqual("m.user");
+    });
+});
+
+describeIfQrCode("QR code verification", () => {
+    test("can verify two users by scanning a QR code", async () => {
+        const [alice, bob, deviceKeys] = await machinesThatKnowEachOther();
+        await crossSignEachOther(alice, bob, deviceKeys);
+
+        const methods = [
+            VerificationMethod.QrCodeScanV1,
+            VerificationMethod.QrCodeShowV1,
+            VerificationMethod.ReciprocateV1,
+        ];
+
+        // Alice requests a verification with Bob's device, and Bob accepts it.
+        const { verificationRequest: aliceRequest, request } = await alice.requestVerification(
+            bob.userId,
+            bob.deviceId,
+            methods,
+        );
+        const flowId = aliceRequest.flowId;
+        await deliver(alice, bob, request);
+
+        const bobRequest = bob.getVerificationRequest(alice.userId, flowId);
+        await deliver(bob, alice, bobRequest.accept(methods));
+
+        expect(aliceRequest.isReady).toStrictEqual(true);
+
+        // Alice shows a QR code, which Bob scans.
+        const aliceQr = await aliceRequest.generateQrCode();
+
+        expect(aliceQr).toBeInstanceOf(Qr);
+        expect(aliceQr.hasBeenScanned).toStrictEqual(false);
+
+        const bobQr = await bobRequest.scanQrCode(aliceQr.toBytes());
+
+        expect(bobQr).toBeInstanceOf(Qr);
+
+        await deliver(bob, alice, bobQr.reciprocate());
+
+        // Alice checks that Bob scanned her QR code, and confirms it.
+        expect(alice.getQr(bob.userId, flowId)).toBeInstanceOf(Qr);
+        expect(aliceQr.hasBeenScanned).toStrictEqual(true);
+
+        await deliver(alice, bob, aliceQr.confirmScanning());
+        expect(aliceQr.hasBeenConfirmed).toStrictEqual(true);
+
+        // Bob sends his `m.key.verification.done` event in turn.
+        await deliverOutgoing(bob, alice);
+
+        expect(aliceQr.isDone).toStrictEqual(true);
+        expect(bobQr.isDone).toStrictEqual(true);
+        expect(aliceRequest.isDone).toStrictEqual(true);
+        expect(bobRequest.isDone).toStrictEqual(true);
+    });
+});